@@ -1,7 +1,9 @@
-use crate::channel::ChannelResponse;
+use crate::channel::{ChannelResponse, HostedChannelResponse};
+use crate::keysend::KeysendResponse;
 use crate::pay::PayResponse;
 use crate::withdraw::WithdrawalResponse;
 use crate::Error as LnUrlError;
+use bitcoin::secp256k1::PublicKey;
 use serde::de::Error;
 use serde::{Deserialize, Serialize};
 use std::fmt::Display;
@@ -15,34 +17,217 @@ pub fn decode_ln_url_response(string: &str) -> Result<LnUrlResponse, LnUrlError>
 pub fn decode_ln_url_response_from_json(
     json: serde_json::Value,
 ) -> Result<LnUrlResponse, LnUrlError> {
+    let response = parse_ln_url_response(json)?;
+    response.validate()?;
+    Ok(response)
+}
+
+/// Parse the response by its `tag` without validating its fields
+fn parse_ln_url_response(json: serde_json::Value) -> Result<LnUrlResponse, LnUrlError> {
     let obj = json.as_object().ok_or(LnUrlError::InvalidResponse)?;
+    if obj.get("status").and_then(|v| v.as_str()) == Some("ERROR") {
+        let reason = obj
+            .get("reason")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default();
+        return Err(LnUrlError::Service(reason.to_string()));
+    }
     let tag_str = obj
         .get("tag")
         .and_then(|v| v.as_str())
         .ok_or(LnUrlError::InvalidResponse)?;
 
     let tag = Tag::from_str(tag_str)?;
-    match tag {
+    let response = match tag {
         Tag::PayRequest => {
             let pay_response: PayResponse = serde_json::from_value(json)?;
-            Ok(LnUrlResponse::LnUrlPayResponse(pay_response))
+            LnUrlResponse::LnUrlPayResponse(pay_response)
         }
         Tag::WithdrawRequest => {
             let resp: WithdrawalResponse = serde_json::from_value(json)?;
-            Ok(LnUrlResponse::LnUrlWithdrawResponse(resp))
+            LnUrlResponse::LnUrlWithdrawResponse(resp)
         }
         Tag::ChannelRequest => {
             let resp: ChannelResponse = serde_json::from_value(json)?;
-            Ok(LnUrlResponse::LnUrlChannelResponse(resp))
+            LnUrlResponse::LnUrlChannelResponse(resp)
+        }
+        Tag::HostedChannelRequest => {
+            let resp: HostedChannelResponse = serde_json::from_value(json)?;
+            LnUrlResponse::LnUrlHostedChannelResponse(resp)
+        }
+        Tag::Keysend => {
+            let resp: KeysendResponse = serde_json::from_value(json)?;
+            LnUrlResponse::LnUrlKeysendResponse(resp)
         }
+        Tag::Unknown(tag) => LnUrlResponse::Unknown { tag, raw: json },
+    };
+
+    Ok(response)
+}
+
+/// Check that `callback` is an http(s) url
+pub(crate) fn validate_callback(callback: &str) -> Result<(), LnUrlError> {
+    match url::Url::parse(callback) {
+        Ok(url) if url.scheme() == "https" || url.scheme() == "http" => Ok(()),
+        Ok(url) => Err(LnUrlError::InvalidField {
+            field: "callback",
+            reason: format!("unsupported scheme {}", url.scheme()),
+        }),
+        Err(e) => Err(LnUrlError::InvalidField {
+            field: "callback",
+            reason: e.to_string(),
+        }),
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+/// Check that the `tag` of a response is `expected`
+pub(crate) fn validate_tag(tag: &Tag, expected: Tag) -> Result<(), LnUrlError> {
+    if *tag != expected {
+        return Err(LnUrlError::InvalidField {
+            field: "tag",
+            reason: format!("expected {expected}, got {tag}"),
+        });
+    }
+
+    Ok(())
+}
+
+/// Check that `uri` is of the form `node_key@host:port`
+pub(crate) fn validate_node_uri(uri: &str) -> Result<(), LnUrlError> {
+    let valid = match uri.split_once('@') {
+        Some((node_key, address)) => {
+            PublicKey::from_str(node_key).is_ok()
+                && address
+                    .rsplit_once(':')
+                    .is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok())
+        }
+        None => false,
+    };
+    if !valid {
+        return Err(LnUrlError::InvalidField {
+            field: "uri",
+            reason: "must be of the form node_key@host:port".to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Check that `k1` is 32 bytes, hex encoded
+pub(crate) fn validate_k1(k1: &str) -> Result<(), LnUrlError> {
+    if k1.len() != 64 || !k1.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(LnUrlError::InvalidField {
+            field: "k1",
+            reason: "must be 32 bytes, hex encoded".to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Remove the explicit `null`s of the optional `fields` from `json` and return them, to be
+/// kept in the `extra` map of the response deserialized from it
+pub(crate) fn take_nulls(
+    json: &mut serde_json::Map<String, serde_json::Value>,
+    fields: &[&str],
+) -> serde_json::Map<String, serde_json::Value> {
+    let mut nulls = serde_json::Map::new();
+    for field in fields {
+        if json.get(*field) == Some(&serde_json::Value::Null) {
+            json.remove(*field);
+            nulls.insert(field.to_string(), serde_json::Value::Null);
+        }
+    }
+
+    nulls
+}
+
+/// `extra` without the `null`s kept of the optional `fields` that have since been set, as
+/// those serialize their value instead, or `None` if it keeps none of them
+pub(crate) fn without_set_nulls(
+    extra: &serde_json::Map<String, serde_json::Value>,
+    fields: &[(&str, bool)],
+) -> Option<serde_json::Map<String, serde_json::Value>> {
+    let is_set = |key: &str| fields.contains(&(key, true));
+    if !extra.keys().any(|key| is_set(key)) {
+        return None;
+    }
+
+    let extra = extra.iter().filter(|(key, _)| !is_set(key));
+    Some(
+        extra
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect(),
+    )
+}
+
+/// A decoded LNURL response.
+///
+/// Serializes to the JSON object sent by the service, and deserializes from it,
+/// discriminated by its `tag`. Unlike [`decode_ln_url_response`], deserializing doesn't
+/// [`validate`](LnUrlResponse::validate) the response, so stored responses always load.
+#[derive(Debug, PartialEq, Clone)]
 pub enum LnUrlResponse {
     LnUrlPayResponse(PayResponse),
     LnUrlWithdrawResponse(WithdrawalResponse),
     LnUrlChannelResponse(ChannelResponse),
+    LnUrlHostedChannelResponse(HostedChannelResponse),
+    LnUrlKeysendResponse(KeysendResponse),
+    /// A response with a tag this library doesn't know, so that wallets can tell the user
+    /// what it is instead of failing
+    Unknown {
+        /// The tag of the response
+        tag: String,
+        /// The JSON of the response
+        raw: serde_json::Value,
+    },
+}
+
+impl LnUrlResponse {
+    /// Check the fields of the response are consistent, see the `validate` method of
+    /// each response type
+    pub fn validate(&self) -> Result<(), LnUrlError> {
+        match self {
+            LnUrlResponse::LnUrlPayResponse(pay) => pay.validate(),
+            LnUrlResponse::LnUrlWithdrawResponse(withdrawal) => withdrawal.validate(),
+            LnUrlResponse::LnUrlChannelResponse(channel) => channel.validate(),
+            LnUrlResponse::LnUrlHostedChannelResponse(hosted) => hosted.validate(),
+            LnUrlResponse::LnUrlKeysendResponse(keysend) => keysend.validate(),
+            LnUrlResponse::Unknown { .. } => Ok(()),
+        }
+    }
+
+    /// The tag of the response
+    pub fn tag(&self) -> Tag {
+        match self {
+            LnUrlResponse::LnUrlPayResponse(_) => Tag::PayRequest,
+            LnUrlResponse::LnUrlWithdrawResponse(_) => Tag::WithdrawRequest,
+            LnUrlResponse::LnUrlChannelResponse(_) => Tag::ChannelRequest,
+            LnUrlResponse::LnUrlHostedChannelResponse(_) => Tag::HostedChannelRequest,
+            LnUrlResponse::LnUrlKeysendResponse(_) => Tag::Keysend,
+            LnUrlResponse::Unknown { tag, .. } => Tag::Unknown(tag.clone()),
+        }
+    }
+}
+
+impl Serialize for LnUrlResponse {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            LnUrlResponse::LnUrlPayResponse(pay) => pay.serialize(serializer),
+            LnUrlResponse::LnUrlWithdrawResponse(withdrawal) => withdrawal.serialize(serializer),
+            LnUrlResponse::LnUrlChannelResponse(channel) => channel.serialize(serializer),
+            LnUrlResponse::LnUrlHostedChannelResponse(hosted) => hosted.serialize(serializer),
+            LnUrlResponse::LnUrlKeysendResponse(keysend) => keysend.serialize(serializer),
+            LnUrlResponse::Unknown { raw, .. } => raw.serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for LnUrlResponse {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let json = serde_json::Value::deserialize(deserializer)?;
+        parse_ln_url_response(json).map_err(D::Error::custom)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -53,6 +238,13 @@ pub enum Tag {
     WithdrawRequest,
     #[serde(rename = "channelRequest")]
     ChannelRequest,
+    #[serde(rename = "hostedChannelRequest")]
+    HostedChannelRequest,
+    #[serde(rename = "keysend")]
+    Keysend,
+    /// Any other tag
+    #[serde(untagged)]
+    Unknown(String),
 }
 
 impl Display for Tag {
@@ -61,6 +253,9 @@ impl Display for Tag {
             Tag::PayRequest => write!(f, "payRequest"),
             Tag::WithdrawRequest => write!(f, "withdrawRequest"),
             Tag::ChannelRequest => write!(f, "channelRequest"),
+            Tag::HostedChannelRequest => write!(f, "hostedChannelRequest"),
+            Tag::Keysend => write!(f, "keysend"),
+            Tag::Unknown(tag) => write!(f, "{tag}"),
         }
     }
 }
@@ -73,7 +268,10 @@ impl FromStr for Tag {
             "payRequest" => Ok(Tag::PayRequest),
             "withdrawRequest" => Ok(Tag::WithdrawRequest),
             "channelRequest" => Ok(Tag::ChannelRequest),
-            _ => Err(serde_json::Error::custom("Unknown tag")),
+            "hostedChannelRequest" => Ok(Tag::HostedChannelRequest),
+            "keysend" => Ok(Tag::Keysend),
+            "" => Err(serde_json::Error::custom("Empty tag")),
+            tag => Ok(Tag::Unknown(tag.to_string())),
         }
     }
 }
@@ -120,6 +318,126 @@ mod tests {
             assert_eq!(resp, test.1);
         }
     }
+    #[test]
+    fn decode_hosted_channel_and_unknown() {
+        let hosted = r#"{"tag":"hostedChannelRequest","uri":"02f1a8c87607f415c8f22c00593002775941dea48869ce23096af27b0cfdcc0b69@127.0.0.1:9735","k1":"e2af6254a8df433264fa23f67eb8188635d15ce883e8fc020989d5f82ae6f11e","alias":"hosted"}"#;
+        match decode_ln_url_response(hosted).unwrap() {
+            LnUrlResponse::LnUrlHostedChannelResponse(resp) => {
+                assert_eq!(resp.alias.as_deref(), Some("hosted"));
+                assert_eq!(resp.tag, Tag::HostedChannelRequest);
+            }
+            other => panic!("expected a hosted channel response, got {:?}", other),
+        }
+
+        let unknown = r#"{"tag":"futureRequest","foo":"bar"}"#;
+        let resp = decode_ln_url_response(unknown).unwrap();
+        assert_eq!(resp.tag(), Tag::Unknown("futureRequest".to_string()));
+        match resp {
+            LnUrlResponse::Unknown { tag, raw } => {
+                assert_eq!(tag, "futureRequest");
+                assert_eq!(raw["foo"], "bar");
+            }
+            other => panic!("expected an unknown response, got {:?}", other),
+        }
+
+        assert_eq!(
+            serde_json::to_string(&Tag::Unknown("futureRequest".to_string())).unwrap(),
+            r#""futureRequest""#
+        );
+        assert_eq!(
+            serde_json::from_str::<Tag>(r#""hostedChannelRequest""#).unwrap(),
+            Tag::HostedChannelRequest
+        );
+    }
+
+    #[test]
+    fn lossless_round_trip() {
+        // hand-written responses carrying vendor extensions, e.g. payer data and currencies
+        let fixtures = [
+            r#"{"callback":"https://legend.lnbits.com/lnurlp/api/v1/lnurl/cb/8Ha9S9","maxSendable":100000000,"minSendable":1000,"metadata":"[[\"text/plain\", \"Payment to ben\"]]","tag":"payRequest","commentAllowed":255,"payerData":{"name":{"mandatory":false},"pubkey":{"mandatory":false},"auth":{"mandatory":false,"k1":"6b2f5a8a86b7a7e2e6f3cf0d0c4b5c1a3a1d2e9f3f5c6a4b1e2d3c4b5a6f7e8d"}},"currencies":[{"code":"USD","name":"US Dollar","symbol":"$","decimals":2,"multiplier":0.0295,"convertible":true}]}"#,
+            r#"{"status":"OK","tag":"payRequest","commentAllowed":255,"callback":"https://getalby.com/lnurlp/ben/callback","metadata":"[[\"text/identifier\",\"ben@getalby.com\"],[\"text/plain\",\"Sats for ben\"]]","minSendable":1000,"maxSendable":500000000,"payerData":{"name":{"mandatory":false},"email":{"mandatory":false}},"nostrPubkey":"79f00d3f5a19ec806189fcab03c1be4ff81d18ee4f653c88fac41fe03570f432","allowsNostr":true}"#,
+            r#"{"tag":"withdrawRequest","callback":"https://legend.lnbits.com/withdraw/api/v1/lnurl/cb/Tg8PQ7","k1":"e2af6254a8df433264fa23f67eb8188635d15ce883e8fc020989d5f82ae6f11e","minWithdrawable":10000,"maxWithdrawable":10000,"defaultDescription":"vouchers","webhook_url":null,"balanceCheck":"https://legend.lnbits.com/withdraw/api/v1/lnurl/Tg8PQ7","payLink":"lnurlp://legend.lnbits.com/lnurlp/api/v1/lnurl/8Ha9S9"}"#,
+            r#"{"tag":"channelRequest","uri":"02f1a8c87607f415c8f22c00593002775941dea48869ce23096af27b0cfdcc0b69@127.0.0.1:9735","callback":"https://lnrouter.app/api/channel","k1":"e2af6254a8df433264fa23f67eb8188635d15ce883e8fc020989d5f82ae6f11e","withdrawLink":"https://lnrouter.app/api/withdraw","privateOnly":true}"#,
+        ];
+
+        for fixture in fixtures {
+            let original: serde_json::Value = serde_json::from_str(fixture).unwrap();
+            let serialized = match decode_ln_url_response(fixture).unwrap() {
+                LnUrlResponse::LnUrlPayResponse(pay) => serde_json::to_value(pay),
+                LnUrlResponse::LnUrlWithdrawResponse(withdraw) => serde_json::to_value(withdraw),
+                LnUrlResponse::LnUrlChannelResponse(channel) => serde_json::to_value(channel),
+                other => panic!("unexpected response {:?}", other),
+            }
+            .unwrap();
+            assert_eq!(serialized, original);
+        }
+    }
+
+    #[test]
+    fn explicit_nulls_round_trip() {
+        let fixtures = [
+            r#"{"tag":"payRequest","callback":"https://example.com/cb","minSendable":1000,"maxSendable":100000,"metadata":"[[\"text/plain\",\"tips\"]]","commentAllowed":null,"allowsNostr":null,"nostrPubkey":null,"webhook_url":null}"#,
+            r#"{"tag":"withdrawRequest","callback":"https://example.com/cb","k1":"e2af6254a8df433264fa23f67eb8188635d15ce883e8fc020989d5f82ae6f11e","minWithdrawable":null,"maxWithdrawable":10000,"defaultDescription":"withdraw"}"#,
+            r#"{"tag":"hostedChannelRequest","uri":"02f1a8c87607f415c8f22c00593002775941dea48869ce23096af27b0cfdcc0b69@127.0.0.1:9735","k1":"e2af6254a8df433264fa23f67eb8188635d15ce883e8fc020989d5f82ae6f11e","alias":null}"#,
+        ];
+
+        for fixture in fixtures {
+            let original: serde_json::Value = serde_json::from_str(fixture).unwrap();
+            let decoded = decode_ln_url_response(fixture).unwrap();
+            assert_eq!(serde_json::to_value(&decoded).unwrap(), original);
+            assert_eq!(
+                serde_json::from_str::<LnUrlResponse>(&serde_json::to_string(&decoded).unwrap())
+                    .unwrap(),
+                decoded
+            );
+        }
+
+        // a field set after decoding serializes its value instead of the kept null
+        let mut pay = match decode_ln_url_response(fixtures[0]).unwrap() {
+            LnUrlResponse::LnUrlPayResponse(pay) => pay,
+            other => panic!("unexpected response {:?}", other),
+        };
+        assert_eq!(pay.comment_allowed, None);
+        pay.comment_allowed = Some(255);
+        let serialized = serde_json::to_string(&pay).unwrap();
+        assert_eq!(serialized.matches("commentAllowed").count(), 1);
+        let serialized: serde_json::Value = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(serialized["commentAllowed"], 255);
+        assert_eq!(serialized["allowsNostr"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn response_serde_is_wire_format() {
+        let json = r#"{"tag":"withdrawRequest","callback":"https://lnurl.com/withdraw","k1":"e2af6254a8df433264fa23f67eb8188635d15ce883e8fc020989d5f82ae6f11e","minWithdrawable":1000,"maxWithdrawable":10000,"defaultDescription":"withdraw"}"#;
+        let decoded = decode_ln_url_response(json).unwrap();
+
+        let serialized = serde_json::to_string(&decoded).unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&serialized).unwrap(),
+            serde_json::from_str::<serde_json::Value>(json).unwrap()
+        );
+        assert_eq!(
+            serde_json::from_str::<LnUrlResponse>(&serialized).unwrap(),
+            decoded
+        );
+
+        let unknown = r#"{"foo":"bar","tag":"futureRequest"}"#;
+        let decoded: LnUrlResponse = serde_json::from_str(unknown).unwrap();
+        assert_eq!(serde_json::to_string(&decoded).unwrap(), unknown);
+
+        assert!(serde_json::from_str::<LnUrlResponse>(r#"{"foo":"bar"}"#).is_err());
+        assert!(matches!(
+            decode_ln_url_response(r#"{"status":"ERROR","reason":"unknown user"}"#),
+            Err(LnUrlError::Service(ref reason)) if reason == "unknown user"
+        ));
+
+        // deserializing doesn't validate, decoding does
+        let invalid = json.replace("\"minWithdrawable\":1000", "\"minWithdrawable\":100000");
+        let deserialized: LnUrlResponse = serde_json::from_str(&invalid).unwrap();
+        assert!(deserialized.validate().is_err());
+        assert!(decode_ln_url_response(&invalid).is_err());
+    }
+
     #[test]
     fn response_to_str() {
         let tests = vec![
@@ -3,18 +3,46 @@
 
 use bitcoin::secp256k1::ecdsa::Signature;
 use bitcoin::secp256k1::PublicKey;
+use bitcoin::Network;
 use reqwest::Client;
+use std::collections::HashMap;
+use std::sync::Arc;
+use url::form_urlencoded::byte_serialize;
+use url::Url;
 
 use crate::api::*;
+use crate::bip353::{
+    dns_name, parse_human_readable_name, select_payment_instructions, AsyncDnsResolver,
+    PaymentTarget,
+};
+use crate::cache::{is_cacheable_url, store, ResponseCache};
 use crate::channel::ChannelResponse;
+use crate::keysend::KeysendResponse;
+use crate::lightning_address::LightningAddress;
 use crate::lnurl::LnUrl;
-use crate::pay::{LnURLPayInvoice, PayResponse, VerifyResponse};
+use crate::observer::RequestObserver;
+use crate::pay::{
+    LnURLPayInvoice, PayOptions, PayResponse, PayTarget, PreparedPayment, VerifyResponse,
+};
+use crate::retry::{host_of, is_transient, retry_after, CircuitBreaker, RequestKind, RetryPolicy};
 use crate::withdraw::WithdrawalResponse;
-use crate::{Builder, Error};
+use crate::{
+    apply_resolve_overrides, parse_resolve_overrides, Builder, Error, DEFAULT_MAX_BODY_SIZE,
+    DEFAULT_MIN_INVOICE_EXPIRY,
+};
+use serde::de::DeserializeOwned;
 
 #[derive(Debug, Clone)]
 pub struct AsyncClient {
     pub client: Client,
+    resolve_overrides: HashMap<String, Url>,
+    retry: Option<RetryPolicy>,
+    circuit_breaker: Option<CircuitBreaker>,
+    cache: Option<Arc<dyn ResponseCache>>,
+    max_body_size: usize,
+    observer: Option<Arc<dyn RequestObserver>>,
+    network: Option<Network>,
+    min_invoice_expiry: u64,
 }
 
 impl Default for AsyncClient {
@@ -25,15 +53,22 @@ impl Default for AsyncClient {
 
 impl AsyncClient {
     pub fn new() -> Self {
-        Self {
-            client: Client::new(),
-        }
+        Self::from_client(Client::new())
     }
 
     /// build an async client from a builder
     pub fn from_builder(builder: Builder) -> Result<Self, Error> {
         let mut client_builder = Client::builder();
 
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(tor) = builder.tor.clone() {
+            tor.validate()?;
+            // takes precedence over the proxy below for the requests it routes
+            client_builder = client_builder.proxy(reqwest::Proxy::custom(move |url| {
+                tor.proxy_for(url.as_str(), "socks5h")
+            }));
+        }
+
         #[cfg(not(target_arch = "wasm32"))]
         if let Some(proxy) = &builder.proxy {
             client_builder = client_builder.proxy(reqwest::Proxy::all(proxy)?);
@@ -44,19 +79,263 @@ impl AsyncClient {
             client_builder = client_builder.timeout(core::time::Duration::from_secs(timeout));
         }
 
-        Ok(Self::from_client(client_builder.build()?))
+        let mut headers = reqwest::header::HeaderMap::new();
+        for (name, value) in &builder.headers {
+            let invalid = || Error::Other(format!("Invalid header {name}: {value}"));
+            headers.insert(
+                reqwest::header::HeaderName::from_bytes(name.as_bytes()).map_err(|_| invalid())?,
+                reqwest::header::HeaderValue::from_str(value).map_err(|_| invalid())?,
+            );
+        }
+        client_builder = client_builder.default_headers(headers);
+
+        if let Some(user_agent) = &builder.user_agent {
+            client_builder = client_builder.user_agent(user_agent);
+        }
+
+        let mut client = Self::from_client(client_builder.build()?);
+        client.resolve_overrides = parse_resolve_overrides(&builder.resolve_overrides)?;
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            client.retry = builder.retry;
+        }
+        client.circuit_breaker = builder.circuit_breaker.map(CircuitBreaker::new);
+        client.cache = builder.cache;
+        client.max_body_size = builder.max_body_size.unwrap_or(DEFAULT_MAX_BODY_SIZE);
+        client.observer = builder.observer;
+        client.network = builder.network;
+        client.min_invoice_expiry = builder
+            .min_invoice_expiry
+            .unwrap_or(DEFAULT_MIN_INVOICE_EXPIRY);
+
+        Ok(client)
     }
 
     /// build an async client from the base url and [`Client`]
     pub fn from_client(client: Client) -> Self {
-        AsyncClient { client }
+        AsyncClient {
+            client,
+            resolve_overrides: HashMap::new(),
+            retry: None,
+            circuit_breaker: None,
+            cache: None,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            observer: None,
+            network: None,
+            min_invoice_expiry: DEFAULT_MIN_INVOICE_EXPIRY,
+        }
+    }
+
+    /// Apply the builder's resolve overrides to `url`
+    fn resolve_url(&self, url: &str) -> String {
+        apply_resolve_overrides(&self.resolve_overrides, url)
+    }
+
+    /// Send a GET request to `url` with extra `headers`, retrying it per the retry policy
+    /// if `kind` allows it.
+    ///
+    /// Error statuses are turned into errors. There are no retries on `wasm32`, see
+    /// [`Builder::retry`].
+    async fn get(
+        &self,
+        url: &str,
+        kind: RequestKind,
+        headers: &[(&str, &str)],
+    ) -> Result<reqwest::Response, Error> {
+        let url = self.resolve_url(url);
+        let host = host_of(&url);
+
+        let mut retries = 0;
+        loop {
+            if let Some(breaker) = &self.circuit_breaker {
+                breaker.check(&host)?;
+            }
+
+            let mut request = self.client.get(&url);
+            for (name, value) in headers {
+                request = request.header(*name, *value);
+            }
+
+            if let Some(observer) = &self.observer {
+                observer.on_request(&url, retries);
+            }
+            let mut delay_hint = None;
+            let result = match request.send().await {
+                Ok(resp) => {
+                    let header = resp.headers().get(reqwest::header::RETRY_AFTER);
+                    delay_hint = retry_after(
+                        resp.status().as_u16(),
+                        header.and_then(|value| value.to_str().ok()),
+                    );
+                    resp.error_for_status().map_err(Error::from)
+                }
+                Err(e) => Err(Error::from(e)),
+            };
+            if let Some(observer) = &self.observer {
+                observer.on_response(&url, result.as_ref().map(|resp| resp.status().as_u16()));
+            }
+
+            if let Some(breaker) = &self.circuit_breaker {
+                breaker.record(&host, result.as_ref().is_err_and(is_transient));
+            }
+
+            let delay = match (&result, &self.retry) {
+                (Err(e), Some(policy)) if policy.should_retry(kind, retries, e) => {
+                    policy.delay(retries, delay_hint)
+                }
+                _ => None,
+            };
+            match delay {
+                Some(_delay) => {
+                    #[cfg(not(target_arch = "wasm32"))]
+                    tokio::time::sleep(_delay).await;
+                    retries += 1;
+                }
+                None => return result,
+            }
+        }
+    }
+
+    /// Read the body of `resp`, failing if it is larger than the maximum body size
+    async fn read_body(&self, mut resp: reqwest::Response) -> Result<String, Error> {
+        let too_large = Error::ResponseTooLarge {
+            limit: self.max_body_size,
+        };
+        if resp
+            .content_length()
+            .is_some_and(|len| len > self.max_body_size as u64)
+        {
+            return Err(too_large);
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let body = {
+            let mut body = Vec::new();
+            while let Some(chunk) = resp.chunk().await? {
+                if body.len() + chunk.len() > self.max_body_size {
+                    return Err(too_large);
+                }
+                body.extend_from_slice(&chunk);
+            }
+            body
+        };
+        #[cfg(target_arch = "wasm32")]
+        let body = resp.bytes().await?.to_vec();
+
+        if body.len() > self.max_body_size {
+            return Err(too_large);
+        }
+
+        String::from_utf8(body).map_err(|_| Error::InvalidResponse)
+    }
+
+    /// Read the body of `resp` as JSON, failing if it is larger than the maximum body size
+    async fn read_json<T: DeserializeOwned>(&self, resp: reqwest::Response) -> Result<T, Error> {
+        Ok(serde_json::from_str(&self.read_body(resp).await?)?)
     }
 
     pub async fn make_request(&self, url: &str) -> Result<LnUrlResponse, Error> {
-        let resp = self.client.get(url).send().await?;
+        let cache = self.cache.as_deref().filter(|_| is_cacheable_url(url));
+        let cached = cache.and_then(|cache| cache.get(url));
+        if let Some(cached) = cached.as_ref().filter(|cached| cached.is_fresh()) {
+            return decode_ln_url_response(&cached.body);
+        }
+
+        let etag = cached.as_ref().and_then(|cached| cached.etag.clone());
+        let headers: Vec<_> = etag.iter().map(|e| ("If-None-Match", e.as_str())).collect();
+        let resp = self.get(url, RequestKind::Idempotent, &headers).await?;
+
+        let header = |name| {
+            resp.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        let cache_control = header(reqwest::header::CACHE_CONTROL);
+        let new_etag = header(reqwest::header::ETAG);
+        // the previous etag only still applies if the server confirmed the cached body
+        let (txt, etag) = match cached {
+            Some(cached) if resp.status() == reqwest::StatusCode::NOT_MODIFIED => {
+                (cached.body, new_etag.or(etag))
+            }
+            _ => (self.read_body(resp).await?, new_etag),
+        };
+
+        let response = decode_ln_url_response(&txt)?;
+        if let Some(cache) = cache {
+            store(
+                cache,
+                url,
+                &response,
+                txt,
+                cache_control.as_deref(),
+                etag.as_deref(),
+            );
+        }
+
+        Ok(response)
+    }
 
-        let txt = resp.error_for_status()?.text().await?;
-        decode_ln_url_response(&txt)
+    /// Resolve a `₿user@domain` human-readable name into a payment target.
+    ///
+    /// BIP-353 payment instructions are looked up with `dns` first, falling back to the
+    /// LUD-16 pay request of the equivalent lightning address if there are none, see
+    /// [`Self::resolve_lightning_address`].
+    pub async fn resolve_human_readable_name<D: AsyncDnsResolver>(
+        &self,
+        dns: &D,
+        name: &str,
+    ) -> Result<PaymentTarget, Error> {
+        let address = parse_human_readable_name(name)?;
+        let records = dns.resolve_txt(&dns_name(&address)).await?;
+        if let Some(uri) = select_payment_instructions(records)? {
+            return Ok(PaymentTarget::Bip353(uri));
+        }
+
+        let pay = self.resolve_lightning_address(&address).await?;
+        Ok(PaymentTarget::LnUrlPay(pay))
+    }
+
+    /// Fetch the pay request of a lightning address, checking that its metadata identifies
+    /// the address as LUD-16 requires.
+    ///
+    /// Returns [`Error::IdentifierMismatch`] if the metadata has no `text/identifier` or
+    /// `text/email` entry equal to `address`.
+    pub async fn resolve_lightning_address(
+        &self,
+        address: &LightningAddress,
+    ) -> Result<PayResponse, Error> {
+        let pay = self.pay_response(&address.lnurlp_url()).await?;
+        pay.verify_identifier(address)?;
+        Ok(pay)
+    }
+
+    /// Fetch the keysend details of a lightning address, for wallets that prefer keysend
+    /// payments where they are offered.
+    ///
+    /// Returns [`Error::WrongTag`] if the service serves something else at the keysend url.
+    pub async fn resolve_keysend(
+        &self,
+        address: &LightningAddress,
+    ) -> Result<KeysendResponse, Error> {
+        match self.make_request(&address.keysend_url()).await? {
+            LnUrlResponse::LnUrlKeysendResponse(keysend) => Ok(keysend),
+            other => Err(Error::WrongTag {
+                expected: Tag::Keysend,
+                found: other.tag(),
+            }),
+        }
+    }
+
+    /// Fetch the pay request at `url`, failing with [`Error::WrongTag`] for other requests
+    async fn pay_response(&self, url: &str) -> Result<PayResponse, Error> {
+        match self.make_request(url).await? {
+            LnUrlResponse::LnUrlPayResponse(pay) => Ok(pay),
+            other => Err(Error::WrongTag {
+                expected: Tag::PayRequest,
+                found: other.tag(),
+            }),
+        }
     }
 
     pub async fn get_invoice(
@@ -67,9 +346,7 @@ impl AsyncClient {
         comment: Option<&str>,
     ) -> Result<LnURLPayInvoice, Error> {
         // verify amount
-        if msats < pay.min_sendable || msats > pay.max_sendable {
-            return Err(Error::InvalidAmount);
-        }
+        pay.check_amount(msats)?;
 
         // verify comment length
         if let Some(comment) = comment {
@@ -82,35 +359,88 @@ impl AsyncClient {
 
         let symbol = if pay.callback.contains('?') { "&" } else { "?" };
 
+        let encode = |value: &str| byte_serialize(value.as_bytes()).collect::<String>();
         let url = match (zap_request, comment) {
             (Some(_), Some(_)) => return Err(Error::InvalidComment),
             (Some(zap_request), None) => format!(
                 "{}{}amount={}&nostr={}",
-                pay.callback, symbol, msats, zap_request
+                pay.callback,
+                symbol,
+                msats,
+                encode(&zap_request)
             ),
             (None, Some(comment)) => format!(
                 "{}{}amount={}&comment={}",
-                pay.callback, symbol, msats, comment
+                pay.callback,
+                symbol,
+                msats,
+                encode(comment)
             ),
             (None, None) => format!("{}{}amount={}", pay.callback, symbol, msats),
         };
 
-        let resp = self.client.get(&url).send().await?;
+        let resp = self.get(&url, RequestKind::Invoice, &[]).await?;
 
-        let invoice: LnURLPayInvoice = resp.error_for_status()?.json().await?;
+        let json: serde_json::Value = self.read_json(resp).await?;
+        let result = serde_json::from_value::<LnURLPayInvoice>(json.clone());
 
-        // verify the returned invoice's amount matches the requested amount (LUD-06)
-        invoice.verify_amount(msats)?;
+        match result {
+            Ok(invoice) => {
+                // verify the returned invoice's amount matches the requested amount (LUD-06)
+                invoice.verify_amount(msats)?;
+                if let Some(network) = self.network {
+                    invoice.verify_network(network)?;
+                }
+                invoice.verify_expiry(self.min_invoice_expiry)?;
+                Ok(invoice)
+            }
+            Err(_) => {
+                let response = serde_json::from_value::<Response<()>>(json)?;
+                match response {
+                    Response::Error { reason } => Err(Error::Service(reason)),
+                    Response::Ok { .. } => Err(Error::InvalidResponse),
+                }
+            }
+        }
+    }
 
-        Ok(invoice)
+    /// Fetch a verified invoice for `msats` from an LNURL, lightning address or pay request url.
+    ///
+    /// Returns [`Error::WrongTag`] if the target is not an LNURL-pay request,
+    /// [`Error::IdentifierMismatch`] if a lightning address is not identified by its pay
+    /// request and [`Error::AmountOutOfRange`] if `msats` is not accepted by the service.
+    pub async fn pay_request(
+        &self,
+        target: impl Into<PayTarget>,
+        msats: u64,
+        options: PayOptions,
+    ) -> Result<PreparedPayment, Error> {
+        let pay = match target.into() {
+            PayTarget::LightningAddress(address) => {
+                self.resolve_lightning_address(&address).await?
+            }
+            target => self.pay_response(&target.url()).await?,
+        };
+        pay.check_amount(msats)?;
+
+        let invoice = self
+            .get_invoice(
+                &pay,
+                msats,
+                options.zap_request.clone(),
+                options.comment.as_deref(),
+            )
+            .await?;
+
+        PreparedPayment::new(pay, invoice, msats, options.zap_request.as_deref())
     }
 
     pub async fn verify(&self, url: &str) -> Result<VerifyResponse, Error> {
-        let resp = self.client.get(url).send().await?;
+        let resp = self.get(url, RequestKind::Idempotent, &[]).await?;
 
-        let rsp: Response<VerifyResponse> = resp.error_for_status()?.json().await?;
+        let rsp: Response<VerifyResponse> = self.read_json(resp).await?;
         match rsp {
-            Response::Error { reason } => Err(Error::Other(reason)),
+            Response::Error { reason } => Err(Error::Service(reason)),
             Response::Ok(r) => Ok(r),
         }
     }
@@ -130,9 +460,9 @@ impl AsyncClient {
             "{}{}k1={}&pr={}",
             withdrawal.callback, symbol, withdrawal.k1, invoice
         );
-        let resp = self.client.get(url).send().await?;
+        let resp = self.get(&url, RequestKind::Once, &[]).await?;
 
-        Ok(resp.error_for_status()?.json().await?)
+        self.read_json(resp).await
     }
 
     pub async fn open_channel(
@@ -156,9 +486,9 @@ impl AsyncClient {
             private as i32 // 0 or 1
         );
 
-        let resp = self.client.get(url).send().await?;
+        let resp = self.get(&url, RequestKind::Once, &[]).await?;
 
-        Ok(resp.error_for_status()?.json().await?)
+        self.read_json(resp).await
     }
 
     pub async fn lnurl_auth(
@@ -167,10 +497,10 @@ impl AsyncClient {
         sig: Signature,
         key: PublicKey,
     ) -> Result<Response<()>, Error> {
-        let url = format!("{}&sig={}&key={}", lnurl.url, sig, key);
+        let url = format!("{}&sig={}&key={}", lnurl.as_str(), sig, key);
 
-        let resp = self.client.get(url).send().await?;
+        let resp = self.get(&url, RequestKind::Once, &[]).await?;
 
-        Ok(resp.error_for_status()?.json().await?)
+        self.read_json(resp).await
     }
 }
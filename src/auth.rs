@@ -1,4 +1,4 @@
-use anyhow::anyhow;
+use crate::Error;
 use bitcoin::bip32::{ChildNumber, DerivationPath};
 use bitcoin::hashes::{sha256, Hash, HashEngine, Hmac, HmacEngine};
 use std::convert::TryInto;
@@ -7,12 +7,14 @@ use url::Url;
 
 /// Derive a derivation path from a hashing key and a url
 /// This is for LUD-05
-pub fn get_derivation_path(hashing_key: [u8; 32], url: &Url) -> anyhow::Result<DerivationPath> {
+pub fn get_derivation_path(hashing_key: [u8; 32], url: &Url) -> Result<DerivationPath, Error> {
     // There exists a private hashingKey which is derived by user LN WALLET using m/138'/0 path.
     let mut engine = HmacEngine::<sha256::Hash>::new(&hashing_key);
 
     // LN SERVICE full domain name is extracted from login LNURL
-    let host = url.host().ok_or(anyhow!("No host"))?;
+    let host = url
+        .host()
+        .ok_or_else(|| Error::Auth("LNURL has no host".to_string()))?;
 
     // and then hashed using hmacSha256(hashingKey, full service domain name)
     engine.input(host.to_string().as_bytes());
@@ -32,7 +34,7 @@ pub fn get_derivation_path(hashing_key: [u8; 32], url: &Url) -> anyhow::Result<D
         "m/138'/{}/{}/{}/{}",
         children[0], children[1], children[2], children[3]
     ))
-    .map_err(|e| anyhow!("Error deriving path: {e}"))?;
+    .map_err(|e| Error::Auth(format!("Error deriving path: {e}")))?;
 
     Ok(path)
 }
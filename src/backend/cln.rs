@@ -0,0 +1,311 @@
+//! [`LightningBackend`] for Core Lightning's JSON-RPC socket.
+
+use crate::backend::{
+    CreatedInvoice, InvoiceDescription, InvoiceState, InvoiceStatus, LightningBackend, Payment,
+};
+use crate::Error;
+use async_trait::async_trait;
+use bitcoin::hashes::hex::FromHex;
+use bitcoin::hashes::sha256;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+
+/// Talks to Core Lightning over its `lightning-rpc` unix socket.
+#[derive(Debug)]
+pub struct ClnRpcBackend {
+    socket_path: PathBuf,
+    next_id: AtomicU64,
+}
+
+#[derive(Deserialize)]
+struct InvoiceResponse {
+    payment_hash: String,
+    bolt11: String,
+}
+
+#[derive(Deserialize)]
+struct PayResponse {
+    payment_preimage: String,
+    amount_msat: u64,
+    amount_sent_msat: u64,
+}
+
+#[derive(Deserialize)]
+struct ListInvoicesResponse {
+    invoices: Vec<ListedInvoice>,
+}
+
+#[derive(Deserialize)]
+struct ListedInvoice {
+    status: String,
+    bolt11: Option<String>,
+    payment_preimage: Option<String>,
+}
+
+impl ClnRpcBackend {
+    /// Connect to the JSON-RPC socket at `socket_path`, usually
+    /// `~/.lightning/<network>/lightning-rpc`
+    pub fn new(socket_path: impl Into<PathBuf>) -> Self {
+        ClnRpcBackend {
+            socket_path: socket_path.into(),
+            next_id: AtomicU64::new(0),
+        }
+    }
+
+    /// A fresh id, for RPC requests and invoice labels
+    fn next_id(&self) -> u64 {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    async fn call<T: DeserializeOwned>(&self, method: &str, params: Value) -> Result<T, Error> {
+        let id = self.next_id();
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params,
+        });
+
+        let mut stream = UnixStream::connect(&self.socket_path).await?;
+        stream.write_all(&serde_json::to_vec(&request)?).await?;
+
+        // responses are not delimited, so read until a whole JSON object has arrived
+        let mut buf = vec![];
+        let response: Value = loop {
+            let mut chunk = [0u8; 4096];
+            let n = stream.read(&mut chunk).await?;
+            if n == 0 {
+                return Err(Error::Backend("connection closed".to_string()));
+            }
+            buf.extend_from_slice(&chunk[..n]);
+
+            match serde_json::Deserializer::from_slice(&buf)
+                .into_iter()
+                .next()
+            {
+                Some(Ok(value)) => break value,
+                Some(Err(e)) if e.is_eof() => continue,
+                Some(Err(e)) => return Err(e.into()),
+                None => continue,
+            }
+        };
+
+        if let Some(error) = response.get("error") {
+            let message = error
+                .get("message")
+                .and_then(Value::as_str)
+                .unwrap_or("unknown error");
+            return Err(Error::Backend(message.to_string()));
+        }
+
+        let result = response
+            .get("result")
+            .cloned()
+            .ok_or(Error::InvalidResponse)?;
+        Ok(serde_json::from_value(result)?)
+    }
+}
+
+fn parse_hex_32(value: &str) -> Result<[u8; 32], Error> {
+    <[u8; 32]>::from_hex(value)
+        .map_err(|_| Error::Backend(format!("invalid 32 byte value: {value}")))
+}
+
+#[async_trait]
+impl LightningBackend for ClnRpcBackend {
+    async fn create_invoice(
+        &self,
+        amount_msats: u64,
+        description: InvoiceDescription,
+        expiry_secs: Option<u32>,
+    ) -> Result<CreatedInvoice, Error> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("time went backwards")
+            .as_nanos();
+        // the id keeps labels unique when invoices are created concurrently
+        let label = format!("lnurl-rs-{nanos}-{}", self.next_id());
+
+        let mut params = json!({
+            "amount_msat": amount_msats,
+            "label": label,
+            "description": description.text(),
+            "deschashonly": description.description_hash().is_some(),
+        });
+        if let Some(expiry) = expiry_secs {
+            params["expiry"] = json!(expiry);
+        }
+
+        let resp: InvoiceResponse = self.call("invoice", params).await?;
+        let payment_hash =
+            sha256::Hash::from_str(&resp.payment_hash).map_err(|_| Error::InvalidResponse)?;
+
+        Ok(CreatedInvoice {
+            bolt11: resp.bolt11,
+            payment_hash,
+        })
+    }
+
+    async fn pay_invoice(&self, bolt11: &str) -> Result<Payment, Error> {
+        let resp: PayResponse = self.call("pay", json!({ "bolt11": bolt11 })).await?;
+
+        Ok(Payment {
+            preimage: parse_hex_32(&resp.payment_preimage)?,
+            fee_msats: resp.amount_sent_msat.checked_sub(resp.amount_msat),
+        })
+    }
+
+    async fn lookup_invoice(&self, payment_hash: sha256::Hash) -> Result<InvoiceState, Error> {
+        let params = json!({ "payment_hash": payment_hash.to_string() });
+        let resp: ListInvoicesResponse = self.call("listinvoices", params).await?;
+        let invoice = resp
+            .invoices
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::Backend("invoice not found".to_string()))?;
+
+        let status = match (invoice.status.as_str(), invoice.payment_preimage) {
+            ("paid", Some(preimage)) => InvoiceStatus::Settled {
+                preimage: parse_hex_32(&preimage)?,
+            },
+            ("expired", _) => InvoiceStatus::Cancelled,
+            _ => InvoiceStatus::Pending,
+        };
+
+        Ok(InvoiceState {
+            bolt11: invoice.bolt11.unwrap_or_default(),
+            status,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bitcoin::hashes::Hash;
+    use std::sync::{Arc, Mutex};
+    use tokio::net::UnixListener;
+
+    /// Answer JSON-RPC requests on a unix socket like Core Lightning would, with the canned
+    /// `(method, response)` pairs, recording the requests
+    fn canned_cln(responses: Vec<(&str, Value)>) -> (ClnRpcBackend, Arc<Mutex<Vec<Value>>>) {
+        let responses: Vec<_> = responses
+            .into_iter()
+            .map(|(method, response)| (method.to_string(), response))
+            .collect();
+        let socket_path = std::env::temp_dir().join(format!(
+            "lnurl-rs-cln-{}-{}",
+            std::process::id(),
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        ));
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        let received = Arc::new(Mutex::new(vec![]));
+
+        let requests = received.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = vec![];
+                let request: Value = loop {
+                    let mut chunk = [0u8; 4096];
+                    let n = stream.read(&mut chunk).await.unwrap();
+                    buf.extend_from_slice(&chunk[..n]);
+                    if let Ok(request) = serde_json::from_slice(&buf) {
+                        break request;
+                    }
+                };
+
+                let response = responses
+                    .iter()
+                    .find(|(method, _)| request["method"] == method.as_str())
+                    .map(|(_, response)| response.clone())
+                    .unwrap_or_else(
+                        || json!({ "error": { "code": -32601, "message": "Unknown command" } }),
+                    );
+                let mut response = response;
+                response["jsonrpc"] = json!("2.0");
+                response["id"] = request["id"].clone();
+                requests.lock().unwrap().push(request);
+
+                // write in two parts, as responses are not delimited
+                let response = serde_json::to_vec(&response).unwrap();
+                let (head, tail) = response.split_at(response.len() / 2);
+                stream.write_all(head).await.unwrap();
+                stream.flush().await.unwrap();
+                stream.write_all(tail).await.unwrap();
+            }
+        });
+
+        (ClnRpcBackend::new(socket_path), received)
+    }
+
+    #[tokio::test]
+    async fn test_create_and_lookup_invoice() {
+        let payment_hash = sha256::Hash::hash(&[1; 32]);
+        let (backend, received) = canned_cln(vec![
+            (
+                "invoice",
+                json!({ "result": { "payment_hash": payment_hash.to_string(), "bolt11": "lnbcrt1" } }),
+            ),
+            (
+                "listinvoices",
+                json!({ "result": { "invoices": [{
+                    "status": "paid",
+                    "bolt11": "lnbcrt1",
+                    "payment_preimage": "01".repeat(32),
+                }] } }),
+            ),
+        ]);
+
+        let description = InvoiceDescription::Hash("[]".to_string());
+        let (first, second) = tokio::join!(
+            backend.create_invoice(21_000, description.clone(), Some(600)),
+            backend.create_invoice(21_000, description, None),
+        );
+        assert_eq!(first.unwrap().payment_hash, payment_hash);
+        assert_eq!(second.unwrap().bolt11, "lnbcrt1");
+
+        let requests = received.lock().unwrap().clone();
+        let params: Vec<_> = requests.iter().map(|r| r["params"].clone()).collect();
+        assert_eq!(params[0]["amount_msat"], 21_000);
+        assert_eq!(params[0]["description"], "[]");
+        assert_eq!(params[0]["deschashonly"], true);
+        assert_ne!(params[0]["label"], params[1]["label"]);
+        assert_ne!(requests[0]["id"], requests[1]["id"]);
+
+        let state = backend.lookup_invoice(payment_hash).await.unwrap();
+        assert_eq!(state.bolt11, "lnbcrt1");
+        assert_eq!(state.status, InvoiceStatus::Settled { preimage: [1; 32] });
+    }
+
+    #[tokio::test]
+    async fn test_pay_invoice() {
+        let (backend, _) = canned_cln(vec![(
+            "pay",
+            json!({ "result": {
+                "payment_preimage": "02".repeat(32),
+                "amount_msat": 21_000,
+                "amount_sent_msat": 21_021,
+            } }),
+        )]);
+
+        let payment = backend.pay_invoice("lnbcrt1").await.unwrap();
+        assert_eq!(payment.preimage, [2; 32]);
+        assert_eq!(payment.fee_msats, Some(21));
+
+        assert!(matches!(
+            backend.lookup_invoice(sha256::Hash::hash(&[])).await,
+            Err(Error::Backend(e)) if e == "Unknown command"
+        ));
+    }
+}
@@ -0,0 +1,344 @@
+//! [`LightningBackend`] for LND's REST API.
+
+use crate::backend::{
+    CreatedInvoice, InvoiceDescription, InvoiceState, InvoiceStatus, LightningBackend, Payment,
+};
+use crate::Error;
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD as BASE64_STANDARD;
+use base64::Engine;
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::hex::DisplayHex;
+use reqwest::{Client, RequestBuilder};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::json;
+use std::convert::TryInto;
+
+/// Talks to LND over its REST API, authenticated with a macaroon.
+///
+/// LND usually serves a self-signed certificate, so the [`Client`] should be built with
+/// it added as a root certificate.
+#[derive(Debug, Clone)]
+pub struct LndRestBackend {
+    client: Client,
+    base_url: String,
+    macaroon: String,
+}
+
+#[derive(Deserialize)]
+struct AddInvoiceResponse {
+    r_hash: String,
+    payment_request: String,
+}
+
+#[derive(Deserialize)]
+struct SendPaymentResponse {
+    #[serde(default)]
+    payment_error: String,
+    #[serde(default)]
+    payment_preimage: String,
+    payment_route: Option<Route>,
+}
+
+#[derive(Deserialize)]
+struct Route {
+    total_fees_msat: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct LookupInvoiceResponse {
+    payment_request: String,
+    state: String,
+    #[serde(default)]
+    r_preimage: String,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    message: String,
+}
+
+impl LndRestBackend {
+    /// Connect to LND's REST API at `base_url`, e.g. `https://localhost:8080`, with a hex
+    /// encoded macaroon
+    pub fn new(base_url: &str, macaroon_hex: &str, client: Client) -> Self {
+        LndRestBackend {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            macaroon: macaroon_hex.to_string(),
+        }
+    }
+
+    async fn send<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T, Error> {
+        let resp = request
+            .header("Grpc-Metadata-macaroon", &self.macaroon)
+            .send()
+            .await?;
+
+        if !resp.status().is_success() {
+            let status = resp.status().as_u16();
+            return match resp.json::<ErrorResponse>().await {
+                Ok(err) => Err(Error::Backend(err.message)),
+                Err(_) => Err(Error::HttpResponse(status)),
+            };
+        }
+
+        Ok(resp.json().await?)
+    }
+}
+
+fn decode_base64_32(value: &str) -> Result<[u8; 32], Error> {
+    BASE64_STANDARD
+        .decode(value)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| Error::Backend(format!("invalid 32 byte value: {value}")))
+}
+
+#[async_trait]
+impl LightningBackend for LndRestBackend {
+    async fn create_invoice(
+        &self,
+        amount_msats: u64,
+        description: InvoiceDescription,
+        expiry_secs: Option<u32>,
+    ) -> Result<CreatedInvoice, Error> {
+        let mut body = json!({ "value_msat": amount_msats.to_string() });
+        match description.description_hash() {
+            Some(hash) => body["description_hash"] = json!(BASE64_STANDARD.encode(hash)),
+            None => body["memo"] = json!(description.text()),
+        }
+        if let Some(expiry) = expiry_secs {
+            body["expiry"] = json!(expiry.to_string());
+        }
+
+        let url = format!("{}/v1/invoices", self.base_url);
+        let resp: AddInvoiceResponse = self.send(self.client.post(url).json(&body)).await?;
+
+        Ok(CreatedInvoice {
+            bolt11: resp.payment_request,
+            payment_hash: sha256::Hash::from_byte_array(decode_base64_32(&resp.r_hash)?),
+        })
+    }
+
+    async fn pay_invoice(&self, bolt11: &str) -> Result<Payment, Error> {
+        let body = json!({ "payment_request": bolt11 });
+        let url = format!("{}/v1/channels/transactions", self.base_url);
+        let resp: SendPaymentResponse = self.send(self.client.post(url).json(&body)).await?;
+
+        if !resp.payment_error.is_empty() {
+            return Err(Error::Backend(resp.payment_error));
+        }
+
+        Ok(Payment {
+            preimage: decode_base64_32(&resp.payment_preimage)?,
+            fee_msats: resp
+                .payment_route
+                .and_then(|route| route.total_fees_msat)
+                .and_then(|fees| fees.parse().ok()),
+        })
+    }
+
+    async fn lookup_invoice(&self, payment_hash: sha256::Hash) -> Result<InvoiceState, Error> {
+        let url = format!(
+            "{}/v1/invoice/{}",
+            self.base_url,
+            payment_hash.as_byte_array().to_lower_hex_string()
+        );
+        let resp: LookupInvoiceResponse = self.send(self.client.get(url)).await?;
+
+        let status = match resp.state.as_str() {
+            "SETTLED" => InvoiceStatus::Settled {
+                preimage: decode_base64_32(&resp.r_preimage)?,
+            },
+            "CANCELED" => InvoiceStatus::Cancelled,
+            _ => InvoiceStatus::Pending,
+        };
+
+        Ok(InvoiceState {
+            bolt11: resp.payment_request,
+            status,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::Value;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    /// A request received by [`canned_lnd`]: the request line, macaroon header and JSON body
+    type Received = (String, Option<String>, Value);
+
+    /// Serve the canned `(request line, status, body)` responses like LND's REST API would,
+    /// recording the requests
+    async fn canned_lnd(
+        responses: Vec<(&str, u16, Value)>,
+    ) -> (LndRestBackend, Arc<Mutex<Vec<Received>>>) {
+        let responses: Vec<_> = responses
+            .into_iter()
+            .map(|(line, status, body)| (line.to_string(), status, body))
+            .collect();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let received = Arc::new(Mutex::new(vec![]));
+
+        let requests = received.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut stream = BufReader::new(stream);
+
+                let mut request_line = String::new();
+                stream.read_line(&mut request_line).await.unwrap();
+                let request_line = request_line.trim_end().trim_end_matches(" HTTP/1.1");
+                let (mut content_length, mut macaroon) = (0, None);
+                loop {
+                    let mut line = String::new();
+                    stream.read_line(&mut line).await.unwrap();
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        break;
+                    }
+                    let (name, value) = line.split_once(": ").unwrap();
+                    match name.to_lowercase().as_str() {
+                        "content-length" => content_length = value.parse().unwrap(),
+                        "grpc-metadata-macaroon" => macaroon = Some(value.to_string()),
+                        _ => {}
+                    }
+                }
+                let mut body = vec![0; content_length];
+                stream.read_exact(&mut body).await.unwrap();
+                let body = serde_json::from_slice(&body).unwrap_or(Value::Null);
+
+                let (status, response) = responses
+                    .iter()
+                    .find(|(line, _, _)| line == request_line)
+                    .map(|(_, status, body)| (*status, body.clone()))
+                    .unwrap_or((404, json!({ "message": "not found" })));
+                requests
+                    .lock()
+                    .unwrap()
+                    .push((request_line.to_string(), macaroon, body));
+
+                let response = response.to_string();
+                let head = format!(
+                    "HTTP/1.1 {status} Canned\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    response.len()
+                );
+                let mut stream = stream.into_inner();
+                stream.write_all(head.as_bytes()).await.unwrap();
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        let backend = LndRestBackend::new(&format!("{base_url}/"), "0201", Client::new());
+        (backend, received)
+    }
+
+    #[tokio::test]
+    async fn test_create_and_lookup_invoice() {
+        let payment_hash = sha256::Hash::hash(&[1; 32]);
+        let lookup = format!(
+            "GET /v1/invoice/{}",
+            payment_hash.as_byte_array().to_lower_hex_string()
+        );
+        let (backend, received) = canned_lnd(vec![
+            (
+                "POST /v1/invoices",
+                200,
+                json!({
+                    "r_hash": BASE64_STANDARD.encode(payment_hash.as_byte_array()),
+                    "payment_request": "lnbcrt1",
+                }),
+            ),
+            (
+                &lookup,
+                200,
+                json!({
+                    "payment_request": "lnbcrt1",
+                    "state": "SETTLED",
+                    "r_preimage": BASE64_STANDARD.encode([1; 32]),
+                }),
+            ),
+        ])
+        .await;
+
+        let description = InvoiceDescription::Hash("[]".to_string());
+        let invoice = backend
+            .create_invoice(21_000, description.clone(), Some(600))
+            .await
+            .unwrap();
+        assert_eq!(invoice.bolt11, "lnbcrt1");
+        assert_eq!(invoice.payment_hash, payment_hash);
+
+        let (line, macaroon, body) = received.lock().unwrap()[0].clone();
+        assert_eq!(line, "POST /v1/invoices");
+        assert_eq!(macaroon.as_deref(), Some("0201"));
+        assert_eq!(
+            body,
+            json!({
+                "value_msat": "21000",
+                "description_hash": BASE64_STANDARD.encode(description.description_hash().unwrap()),
+                "expiry": "600",
+            })
+        );
+
+        let state = backend.lookup_invoice(payment_hash).await.unwrap();
+        assert_eq!(state.bolt11, "lnbcrt1");
+        assert_eq!(state.status, InvoiceStatus::Settled { preimage: [1; 32] });
+    }
+
+    #[tokio::test]
+    async fn test_pay_invoice() {
+        let (backend, _) = canned_lnd(vec![(
+            "POST /v1/channels/transactions",
+            200,
+            json!({
+                "payment_error": "",
+                "payment_preimage": BASE64_STANDARD.encode([2; 32]),
+                "payment_route": { "total_fees_msat": "1000" },
+            }),
+        )])
+        .await;
+
+        let payment = backend.pay_invoice("lnbcrt1").await.unwrap();
+        assert_eq!(payment.preimage, [2; 32]);
+        assert_eq!(payment.fee_msats, Some(1_000));
+
+        let (failing, _) = canned_lnd(vec![(
+            "POST /v1/channels/transactions",
+            200,
+            json!({ "payment_error": "no route" }),
+        )])
+        .await;
+        assert!(matches!(
+            failing.pay_invoice("lnbcrt1").await,
+            Err(Error::Backend(e)) if e == "no route"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_error_response() {
+        let (backend, _) = canned_lnd(vec![(
+            "POST /v1/invoices",
+            500,
+            json!({ "code": 2, "message": "invoice expiry too large" }),
+        )])
+        .await;
+
+        let description = InvoiceDescription::Direct("test".to_string());
+        assert!(matches!(
+            backend.create_invoice(1_000, description, None).await,
+            Err(Error::Backend(e)) if e == "invoice expiry too large"
+        ));
+        assert!(matches!(
+            backend.lookup_invoice(sha256::Hash::hash(&[])).await,
+            Err(Error::Backend(e)) if e == "not found"
+        ));
+    }
+}
@@ -0,0 +1,269 @@
+//! In-memory [`LightningBackend`] for tests.
+
+use crate::backend::{
+    CreatedInvoice, InvoiceDescription, InvoiceState, InvoiceStatus, LightningBackend, Payment,
+};
+use crate::bolt11::{Description, UnsignedInvoice};
+use crate::Error;
+use async_trait::async_trait;
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::secp256k1::rand::random;
+use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+use bitcoin::Network;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Default invoice expiry, per BOLT11
+const DEFAULT_EXPIRY_SECS: u32 = 3600;
+
+#[derive(Debug, Clone)]
+struct MockInvoice {
+    bolt11: String,
+    preimage: [u8; 32],
+    settled: bool,
+}
+
+/// A [`LightningBackend`] that mints real signed invoices without a lightning node.
+///
+/// Invoices it creates can be settled with [`MockLightningBackend::settle`] or by paying
+/// them with [`LightningBackend::pay_invoice`]. Paying any other invoice always succeeds
+/// with a random pre-image and is recorded in [`MockLightningBackend::payments`].
+#[derive(Debug)]
+pub struct MockLightningBackend {
+    network: Network,
+    node_key: SecretKey,
+    invoices: Mutex<HashMap<sha256::Hash, MockInvoice>>,
+    payments: Mutex<Vec<String>>,
+}
+
+impl Default for MockLightningBackend {
+    fn default() -> Self {
+        Self::new(Network::Regtest)
+    }
+}
+
+impl MockLightningBackend {
+    /// Create a mock node with a random key, minting invoices for `network`
+    pub fn new(network: Network) -> Self {
+        Self::with_key(
+            network,
+            SecretKey::from_slice(&random::<[u8; 32]>()).unwrap(),
+        )
+    }
+
+    /// Create a mock node signing its invoices with `node_key`
+    pub fn with_key(network: Network, node_key: SecretKey) -> Self {
+        MockLightningBackend {
+            network,
+            node_key,
+            invoices: Mutex::new(HashMap::new()),
+            payments: Mutex::new(vec![]),
+        }
+    }
+
+    /// The public key invoices are signed with
+    pub fn node_id(&self) -> PublicKey {
+        PublicKey::from_secret_key(&Secp256k1::signing_only(), &self.node_key)
+    }
+
+    /// Create a signed invoice without going through the async trait
+    pub fn create_invoice_sync(
+        &self,
+        amount_msats: u64,
+        description: &InvoiceDescription,
+        expiry_secs: Option<u32>,
+    ) -> CreatedInvoice {
+        let preimage = random::<[u8; 32]>();
+        let payment_hash = sha256::Hash::hash(&preimage);
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("time went backwards")
+            .as_secs();
+
+        let bolt11 = UnsignedInvoice {
+            network: self.network,
+            amount_msats: Some(amount_msats),
+            timestamp,
+            payment_hash,
+            payment_secret: random(),
+            description: match description.description_hash() {
+                Some(hash) => Description::Hash(hash),
+                None => Description::Direct(description.text()),
+            },
+            expiry_secs: Some(expiry_secs.unwrap_or(DEFAULT_EXPIRY_SECS).into()),
+            min_final_cltv_expiry_delta: 18,
+        }
+        .sign(&self.node_key);
+
+        self.invoices.lock().unwrap().insert(
+            payment_hash,
+            MockInvoice {
+                bolt11: bolt11.clone(),
+                preimage,
+                settled: false,
+            },
+        );
+
+        CreatedInvoice {
+            bolt11,
+            payment_hash,
+        }
+    }
+
+    /// Mark one of our invoices as paid, returning its pre-image
+    pub fn settle(&self, payment_hash: sha256::Hash) -> Option<[u8; 32]> {
+        let mut invoices = self.invoices.lock().unwrap();
+        let invoice = invoices.get_mut(&payment_hash)?;
+        invoice.settled = true;
+        Some(invoice.preimage)
+    }
+
+    /// The invoices paid with [`LightningBackend::pay_invoice`], in order
+    pub fn payments(&self) -> Vec<String> {
+        self.payments.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl LightningBackend for MockLightningBackend {
+    async fn create_invoice(
+        &self,
+        amount_msats: u64,
+        description: InvoiceDescription,
+        expiry_secs: Option<u32>,
+    ) -> Result<CreatedInvoice, Error> {
+        Ok(self.create_invoice_sync(amount_msats, &description, expiry_secs))
+    }
+
+    async fn pay_invoice(&self, bolt11: &str) -> Result<Payment, Error> {
+        let mut invoices = self.invoices.lock().unwrap();
+        let preimage = match invoices.values_mut().find(|i| i.bolt11 == bolt11) {
+            Some(invoice) if invoice.settled => {
+                return Err(Error::Backend("invoice is already paid".to_string()))
+            }
+            Some(invoice) => {
+                invoice.settled = true;
+                invoice.preimage
+            }
+            None => random(),
+        };
+        self.payments.lock().unwrap().push(bolt11.to_string());
+
+        Ok(Payment {
+            preimage,
+            fee_msats: Some(0),
+        })
+    }
+
+    async fn lookup_invoice(&self, payment_hash: sha256::Hash) -> Result<InvoiceState, Error> {
+        let invoices = self.invoices.lock().unwrap();
+        let invoice = invoices
+            .get(&payment_hash)
+            .ok_or_else(|| Error::Backend("invoice not found".to_string()))?;
+
+        let status = if invoice.settled {
+            InvoiceStatus::Settled {
+                preimage: invoice.preimage,
+            }
+        } else {
+            InvoiceStatus::Pending
+        };
+
+        Ok(InvoiceState {
+            bolt11: invoice.bolt11.clone(),
+            status,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::backend::{create_pay_invoice, pay_withdrawal, verify_invoice};
+    use crate::fixtures::{pay_response, withdrawal};
+
+    #[tokio::test]
+    async fn test_pay_invoice_and_verify() {
+        let node = MockLightningBackend::default();
+        let pay = pay_response(r#"[["text/plain","test"]]"#);
+
+        let invoice = create_pay_invoice(&node, &pay, 21_000, None).await.unwrap();
+        assert!(invoice.invoice().starts_with("lnbcrt210n1"));
+        invoice.verify_amount(21_000).unwrap();
+
+        assert!(matches!(
+            create_pay_invoice(&node, &pay, 1, None).await,
+            Err(Error::AmountOutOfRange { .. })
+        ));
+
+        let payment_hash = *node.invoices.lock().unwrap().keys().next().unwrap();
+        let verify = verify_invoice(&node, payment_hash).await.unwrap();
+        assert!(!verify.settled);
+        assert_eq!(verify.pr, invoice.pr);
+
+        let payment = node.pay_invoice(&invoice.pr).await.unwrap();
+        let verify = verify_invoice(&node, payment_hash).await.unwrap();
+        assert!(verify.settled);
+        assert_eq!(
+            sha256::Hash::hash(&payment.preimage),
+            payment_hash,
+            "pre-image must match the payment hash"
+        );
+        assert!(node.pay_invoice(&invoice.pr).await.is_err());
+        assert_eq!(node.payments(), vec![invoice.pr]);
+    }
+
+    #[cfg(feature = "axum")]
+    #[tokio::test]
+    async fn test_lightning_address_pay_backend_rejects_zaps() {
+        use crate::backend::LightningAddressPayBackend;
+        use crate::server::axum::{PayBackend, PayCallbackQuery};
+        use crate::server::LightningAddressServer;
+        use std::collections::HashMap;
+
+        let mut registry = HashMap::new();
+        registry.insert(
+            "alice".to_string(),
+            pay_response(r#"[["text/plain","test"]]"#),
+        );
+        let server = LightningAddressServer::new("example.com", registry);
+        let backend = LightningAddressPayBackend::new(server, MockLightningBackend::default());
+
+        let query = PayCallbackQuery {
+            amount: 21_000,
+            comment: None,
+            nostr: None,
+        };
+        assert!(backend.get_invoice("alice", query.clone()).await.is_ok());
+
+        let zap = PayCallbackQuery {
+            nostr: Some(r#"{"kind":9734}"#.to_string()),
+            ..query
+        };
+        assert!(matches!(
+            backend.get_invoice("alice", zap).await,
+            Err(Error::InvalidField { field: "nostr", .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_pay_withdrawal() {
+        let node = MockLightningBackend::default();
+        let wallet = MockLightningBackend::default();
+        let withdrawal = withdrawal();
+
+        let description = InvoiceDescription::Direct("withdraw".to_string());
+        let invoice = wallet.create_invoice_sync(5_000, &description, None);
+        pay_withdrawal(&node, &withdrawal, &invoice.bolt11)
+            .await
+            .unwrap();
+        assert_eq!(node.payments(), vec![invoice.bolt11]);
+
+        let too_large = wallet.create_invoice_sync(20_000, &description, None);
+        assert!(matches!(
+            pay_withdrawal(&node, &withdrawal, &too_large.bolt11).await,
+            Err(Error::InvalidAmount)
+        ));
+    }
+}
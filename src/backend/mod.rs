@@ -0,0 +1,258 @@
+//! Lightning node backends for serving LNURL requests.
+//!
+//! A [`LightningBackend`] creates, pays and looks up invoices on a lightning node. Adapters
+//! are available for LND's REST API (`lnd` feature), Core Lightning's JSON-RPC (`cln` feature)
+//! and an in-memory mock for tests (`mock` feature). There is no LDK adapter: LDK nodes are
+//! embedded in the application, which implements [`LightningBackend`] on top of its own node.
+
+#[cfg(all(feature = "cln", unix))]
+pub mod cln;
+#[cfg(feature = "lnd")]
+pub mod lnd;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+
+use crate::bolt11;
+use crate::pay::{LnURLPayInvoice, PayResponse, VerifyResponse};
+#[cfg(feature = "axum")]
+use crate::server::axum::{PayBackend, PayCallbackQuery};
+#[cfg(feature = "axum")]
+use crate::server::{LightningAddressServer, UserRegistry};
+use crate::withdraw::WithdrawalResponse;
+use crate::Error;
+use async_trait::async_trait;
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::hex::DisplayHex;
+use std::sync::Arc;
+
+/// What an invoice's description commits to
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum InvoiceDescription {
+    /// Include the description itself in the invoice
+    Direct(String),
+    /// Include only the SHA256 of the description in the invoice, as LNURL-pay requires
+    Hash(String),
+}
+
+impl InvoiceDescription {
+    /// The description for an invoice paying `pay`, committing to its metadata per LUD-06
+    pub fn for_pay_response(pay: &PayResponse) -> Self {
+        InvoiceDescription::Hash(pay.metadata.clone())
+    }
+
+    /// The description text
+    pub fn text(&self) -> &str {
+        match self {
+            InvoiceDescription::Direct(text) | InvoiceDescription::Hash(text) => text,
+        }
+    }
+
+    /// The description hash the invoice will commit to, if any
+    pub fn description_hash(&self) -> Option<sha256::Hash> {
+        match self {
+            InvoiceDescription::Direct(_) => None,
+            InvoiceDescription::Hash(text) => Some(sha256::Hash::hash(text.as_bytes())),
+        }
+    }
+}
+
+/// An invoice created by a [`LightningBackend`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CreatedInvoice {
+    /// Encoded bolt 11 invoice
+    pub bolt11: String,
+    /// Payment hash of the invoice
+    pub payment_hash: sha256::Hash,
+}
+
+/// A successful payment made by a [`LightningBackend`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Payment {
+    /// Pre-image of the paid invoice
+    pub preimage: [u8; 32],
+    /// Fees paid, in millisatoshis, if known
+    pub fee_msats: Option<u64>,
+}
+
+/// Settlement status of an invoice
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum InvoiceStatus {
+    /// Not paid yet
+    Pending,
+    /// Paid
+    Settled {
+        /// Pre-image of the invoice
+        preimage: [u8; 32],
+    },
+    /// Expired or cancelled without being paid
+    Cancelled,
+}
+
+/// The state of an invoice, as returned by [`LightningBackend::lookup_invoice`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct InvoiceState {
+    /// Encoded bolt 11 invoice
+    pub bolt11: String,
+    /// Settlement status
+    pub status: InvoiceStatus,
+}
+
+impl InvoiceState {
+    /// The LUD-21 verify response for this invoice
+    pub fn verify_response(&self) -> VerifyResponse {
+        let preimage = match &self.status {
+            InvoiceStatus::Settled { preimage } => Some(preimage.to_lower_hex_string()),
+            _ => None,
+        };
+
+        VerifyResponse {
+            settled: preimage.is_some(),
+            preimage,
+            pr: self.bolt11.clone(),
+        }
+    }
+}
+
+/// A lightning node that can create, pay and look up invoices.
+#[async_trait]
+pub trait LightningBackend: Send + Sync {
+    /// Create an invoice for `amount_msats`, expiring after `expiry_secs` (or the node's default)
+    async fn create_invoice(
+        &self,
+        amount_msats: u64,
+        description: InvoiceDescription,
+        expiry_secs: Option<u32>,
+    ) -> Result<CreatedInvoice, Error>;
+
+    /// Pay a bolt 11 invoice, waiting for the payment to complete
+    async fn pay_invoice(&self, bolt11: &str) -> Result<Payment, Error>;
+
+    /// Look up an invoice created by this node
+    async fn lookup_invoice(&self, payment_hash: sha256::Hash) -> Result<InvoiceState, Error>;
+}
+
+#[async_trait]
+impl<T: LightningBackend + ?Sized> LightningBackend for Arc<T> {
+    async fn create_invoice(
+        &self,
+        amount_msats: u64,
+        description: InvoiceDescription,
+        expiry_secs: Option<u32>,
+    ) -> Result<CreatedInvoice, Error> {
+        (**self)
+            .create_invoice(amount_msats, description, expiry_secs)
+            .await
+    }
+
+    async fn pay_invoice(&self, bolt11: &str) -> Result<Payment, Error> {
+        (**self).pay_invoice(bolt11).await
+    }
+
+    async fn lookup_invoice(&self, payment_hash: sha256::Hash) -> Result<InvoiceState, Error> {
+        (**self).lookup_invoice(payment_hash).await
+    }
+}
+
+/// Create the invoice answering an LNURL-pay callback for `pay`.
+///
+/// The invoice commits to the metadata of `pay` and `verify_url`, if given, is returned as
+/// the LUD-21 verify URL.
+pub async fn create_pay_invoice<L: LightningBackend + ?Sized>(
+    backend: &L,
+    pay: &PayResponse,
+    amount_msats: u64,
+    verify_url: Option<String>,
+) -> Result<LnURLPayInvoice, Error> {
+    pay.check_amount(amount_msats)?;
+
+    let invoice = backend
+        .create_invoice(
+            amount_msats,
+            InvoiceDescription::for_pay_response(pay),
+            None,
+        )
+        .await?;
+
+    let mut invoice = LnURLPayInvoice::new(invoice.bolt11);
+    invoice.verify = verify_url;
+    Ok(invoice)
+}
+
+/// Answer a LUD-21 verify request for the invoice with `payment_hash`
+pub async fn verify_invoice<L: LightningBackend + ?Sized>(
+    backend: &L,
+    payment_hash: sha256::Hash,
+) -> Result<VerifyResponse, Error> {
+    Ok(backend
+        .lookup_invoice(payment_hash)
+        .await?
+        .verify_response())
+}
+
+/// Pay the invoice of an LNURL-withdraw callback for `withdrawal`.
+///
+/// The invoice amount is checked against the limits of `withdrawal`. Checking `k1` and
+/// making sure the withdrawal is only paid once is left to the caller.
+pub async fn pay_withdrawal<L: LightningBackend + ?Sized>(
+    backend: &L,
+    withdrawal: &WithdrawalResponse,
+    invoice: &str,
+) -> Result<Payment, Error> {
+    let msats = bolt11::parse_amount_msats(invoice)?.ok_or(Error::InvalidAmount)?;
+    let min = withdrawal.min_withdrawable.unwrap_or(1);
+    if msats < min || msats > withdrawal.max_withdrawable {
+        return Err(Error::InvalidAmount);
+    }
+
+    backend.pay_invoice(invoice).await
+}
+
+/// Serves LNURL-pay requests for the users of a [`LightningAddressServer`], with invoices
+/// created by a [`LightningBackend`].
+///
+/// Mount it with [`crate::server::axum::LnUrlRouter::pay`] at the path the callbacks of the
+/// registry's pay responses point to.
+///
+/// Zaps are not supported: callbacks with a `nostr` zap request are rejected, as the invoice
+/// would have to commit to the zap request and a zap receipt be published once it is paid.
+#[cfg(feature = "axum")]
+#[derive(Debug, Clone)]
+pub struct LightningAddressPayBackend<R, L> {
+    server: LightningAddressServer<R>,
+    node: L,
+}
+
+#[cfg(feature = "axum")]
+impl<R, L> LightningAddressPayBackend<R, L> {
+    pub fn new(server: LightningAddressServer<R>, node: L) -> Self {
+        LightningAddressPayBackend { server, node }
+    }
+}
+
+#[cfg(feature = "axum")]
+#[async_trait]
+impl<R, L> PayBackend for LightningAddressPayBackend<R, L>
+where
+    R: UserRegistry + Send + Sync + 'static,
+    L: LightningBackend + 'static,
+{
+    async fn pay_response(&self, id: &str) -> Result<PayResponse, Error> {
+        self.server.pay_response(id)
+    }
+
+    async fn get_invoice(
+        &self,
+        id: &str,
+        query: PayCallbackQuery,
+    ) -> Result<LnURLPayInvoice, Error> {
+        if query.nostr.is_some() {
+            return Err(Error::InvalidField {
+                field: "nostr",
+                reason: "zaps are not supported".to_string(),
+            });
+        }
+
+        let pay = self.server.pay_response(id)?;
+        create_pay_invoice(&self.node, &pay, query.amount, None).await
+    }
+}
@@ -0,0 +1,364 @@
+//! Command-line tool for decoding, resolving and paying LNURLs.
+//!
+//! Every command prints pretty JSON on success. On failure the error is printed to stderr
+//! and the process exits with a code depending on the kind of error, see [`exit_code`].
+#![allow(clippy::result_large_err, clippy::large_enum_variant)]
+
+use bitcoin::bip32::{ChildNumber, DerivationPath, Xpriv};
+use bitcoin::hashes::hex::FromHex;
+use bitcoin::secp256k1::{Message, Secp256k1};
+use bitcoin::Network;
+use clap::{Parser, Subcommand};
+use lnurl::lightning_address::LightningAddress;
+use lnurl::lnurl::{LnUrl, LnUrlKind};
+use lnurl::pay::{PayOptions, PayTarget};
+use lnurl::{get_derivation_path, BlockingClient, Builder, Error, LnUrlResponse, Response};
+use serde_json::{json, Value};
+use std::io::{self, BufRead};
+use std::process;
+use std::str::FromStr;
+
+/// Environment variable holding the wallet seed for `auth`
+const SEED_ENV: &str = "LNURL_SEED";
+
+#[derive(Parser)]
+#[command(name = "lnurl", version, about = "Decode, resolve and pay LNURLs")]
+struct Cli {
+    /// Proxy to send requests through, e.g. `socks5://127.0.0.1:9050`
+    #[arg(long, global = true)]
+    proxy: Option<String>,
+    /// Request timeout, in seconds
+    #[arg(long, global = true)]
+    timeout: Option<u64>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Decode a bech32 LNURL into its url
+    Decode { lnurl: String },
+    /// Encode a url as a bech32 LNURL
+    Encode { url: String },
+    /// Fetch the LNURL response of an LNURL, lightning address or url
+    Resolve { target: String },
+    /// Fetch a verified invoice for `msats` from a pay request
+    Invoice {
+        target: String,
+        msats: u64,
+        /// Comment to send along with the payment
+        #[arg(long)]
+        comment: Option<String>,
+    },
+    /// Submit an invoice to a withdraw request
+    Withdraw { target: String, invoice: String },
+    /// Log in to an LNURL-auth service, deriving the linking key from a wallet seed.
+    ///
+    /// The hex encoded BIP32 seed of the wallet is given with `--seed`, or else read from
+    /// `LNURL_SEED` or the first line of stdin, which keep it out of the shell history and
+    /// process list.
+    Auth {
+        lnurl: String,
+        /// Hex encoded BIP32 seed of the wallet
+        #[arg(long)]
+        seed: Option<String>,
+    },
+    /// Check whether an invoice has been paid, using its LUD-21 verify url
+    Verify { url: String },
+}
+
+/// The exit code for `error`:
+///
+/// - 1: any other error
+/// - 2: invalid command-line arguments
+/// - 3: invalid LNURL, lightning address, amount or comment
+/// - 4: network or HTTP error
+/// - 5: invalid response from the service
+/// - 6: the service answered with an LNURL error
+fn exit_code(error: &Error) -> i32 {
+    match error {
+        Error::InvalidLnUrl
+        | Error::InvalidLightningAddress
+        | Error::InvalidComment
+        | Error::InvalidAmount
+        | Error::AmountOutOfRange { .. }
+        | Error::Url(_) => 3,
+        Error::Ureq(_)
+        | Error::HttpResponse(_)
+        | Error::Io(_)
+        | Error::Dns(_)
+        | Error::CircuitOpen(_) => 4,
+        #[cfg(any(feature = "async", feature = "async-https"))]
+        Error::Reqwest(_) => 4,
+        Error::Json(_)
+        | Error::InvalidResponse
+        | Error::InvalidField { .. }
+        | Error::ResponseTooLarge { .. }
+        | Error::WrongTag { .. }
+        | Error::InvalidInvoice(_)
+        | Error::InvoiceAmountMismatch { .. }
+        | Error::InvoiceNetworkMismatch { .. }
+        | Error::InvoiceDescriptionHashMismatch { .. }
+        | Error::InvoiceExpired { .. }
+        | Error::InvoiceExpiresSoon { .. }
+        | Error::IdentifierMismatch { .. } => 5,
+        Error::Service(_) => 6,
+        _ => 1,
+    }
+}
+
+/// The acknowledgement of the service, failing on an LNURL error
+fn ack(response: Response<()>) -> Result<Value, Error> {
+    match response {
+        Response::Ok(()) => Ok(json!({ "status": "OK" })),
+        Response::Error { reason } => Err(Error::Service(reason)),
+    }
+}
+
+/// Read the wallet seed from [`SEED_ENV`], or else from the first line of stdin
+fn read_seed() -> Result<String, Error> {
+    if let Ok(seed) = std::env::var(SEED_ENV) {
+        return Ok(seed.trim().to_string());
+    }
+    let mut seed = String::new();
+    io::stdin().lock().read_line(&mut seed)?;
+    Ok(seed.trim().to_string())
+}
+
+/// The LNURL of a withdraw request, given as a bech32 LNURL, a url or a lightning address,
+/// with or without a `lightning:` prefix
+fn withdraw_lnurl(target: &str) -> Result<LnUrl, Error> {
+    let target = target.trim();
+    let target = match target.get(..10) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("lightning:") => &target[10..],
+        _ => target,
+    };
+
+    LnUrl::from_str(target)
+        .or_else(|_| LnUrl::from_url(target.to_string()))
+        .or_else(|_| LightningAddress::from_str(target).map(|address| address.lnurl()))
+        .map_err(|_| Error::InvalidLnUrl)
+}
+
+/// Derive the LUD-05 linking key for `lnurl` from `seed` and sign its `k1`
+fn auth(client: &BlockingClient, lnurl: &str, seed: &str) -> Result<Value, Error> {
+    let lnurl = LnUrl::from_str(lnurl)?;
+    let k1 = match lnurl.kind() {
+        LnUrlKind::Auth { k1, .. } => <[u8; 32]>::from_hex(&k1).map_err(|_| Error::InvalidLnUrl)?,
+        _ => return Err(Error::InvalidLnUrl),
+    };
+    let seed = Vec::<u8>::from_hex(seed).map_err(|e| Error::Auth(format!("Invalid seed: {e}")))?;
+
+    let secp = Secp256k1::new();
+    let bip32_err = |e: bitcoin::bip32::Error| Error::Auth(e.to_string());
+    let master = Xpriv::new_master(Network::Bitcoin, &seed).map_err(bip32_err)?;
+    let hashing_path = DerivationPath::from(vec![
+        ChildNumber::from_hardened_idx(138).map_err(bip32_err)?,
+        ChildNumber::from_normal_idx(0).map_err(bip32_err)?,
+    ]);
+    let hashing_key = master
+        .derive_priv(&secp, &hashing_path)
+        .map_err(bip32_err)?
+        .private_key
+        .secret_bytes();
+    let linking_path = get_derivation_path(hashing_key, lnurl.url())?;
+    let linking_key = master
+        .derive_priv(&secp, &linking_path)
+        .map_err(bip32_err)?
+        .private_key;
+
+    let sig = secp.sign_ecdsa(&Message::from_digest(k1), &linking_key);
+    let key = linking_key.public_key(&secp);
+    ack(client.lnurl_auth(lnurl, sig, key)?)
+}
+
+/// Run the command, returning the JSON to print
+fn run(cli: Cli) -> Result<Value, Error> {
+    let mut builder = Builder::default();
+    if let Some(proxy) = &cli.proxy {
+        builder = builder.proxy(proxy);
+    }
+    if let Some(timeout) = cli.timeout {
+        builder = builder.timeout(timeout);
+    }
+    let client = builder.build_blocking()?;
+
+    let output = match cli.command {
+        Command::Decode { lnurl } => {
+            let lnurl = LnUrl::from_str(&lnurl)?;
+            json!({
+                "url": lnurl.as_str(),
+                "is_lnurl_auth": lnurl.is_lnurl_auth(),
+                "lightning_address": lnurl.lightning_address().map(|a| a.to_string()),
+            })
+        }
+        Command::Encode { url } => json!({ "lnurl": LnUrl::from_url(url)?.encode()? }),
+        Command::Resolve { target } => {
+            let target = PayTarget::from_str(&target)?;
+            let response = client.make_request(&target.url())?;
+            serde_json::to_value(response)?
+        }
+        Command::Invoice {
+            target,
+            msats,
+            comment,
+        } => {
+            let target = PayTarget::from_str(&target)?;
+            let options = PayOptions {
+                comment,
+                ..Default::default()
+            };
+            let payment = client.pay_request(target, msats, options)?;
+            json!({
+                "invoice": payment.invoice,
+                "amount_msats": payment.amount_msats,
+                "description": payment.metadata.description(),
+                "pay_response": payment.pay_response,
+            })
+        }
+        Command::Withdraw { target, invoice } => {
+            let lnurl = withdraw_lnurl(&target)?;
+            let withdrawal = match client.make_request(lnurl.as_str())? {
+                LnUrlResponse::LnUrlWithdrawResponse(withdrawal) => withdrawal,
+                other => {
+                    return Err(Error::WrongTag {
+                        expected: lnurl::Tag::WithdrawRequest,
+                        found: other.tag(),
+                    })
+                }
+            };
+            ack(client.do_withdrawal(&withdrawal, &invoice)?)?
+        }
+        Command::Auth { lnurl, seed } => {
+            let seed = match seed {
+                Some(seed) => seed,
+                None => read_seed()?,
+            };
+            auth(&client, &lnurl, &seed)?
+        }
+        Command::Verify { url } => serde_json::to_value(client.verify(&url)?)?,
+    };
+
+    Ok(output)
+}
+
+fn main() {
+    let cli = Cli::parse();
+    match run(cli) {
+        Ok(output) => println!(
+            "{}",
+            serde_json::to_string_pretty(&output).expect("values serialize")
+        ),
+        Err(e) => {
+            eprintln!("error: {e}");
+            process::exit(exit_code(&e));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn run_args(args: &[&str]) -> Result<Value, Error> {
+        run(Cli::parse_from(
+            std::iter::once("lnurl").chain(args.iter().copied()),
+        ))
+    }
+
+    #[test]
+    fn test_decode_encode_round_trip() {
+        let url = "https://service.com/api?q=3fc3645b439ce8e7f2553a69e5267081d96dcd340693afabe04be7b0ccd178df";
+        let encoded = run_args(&["encode", url]).unwrap();
+        let lnurl = encoded["lnurl"].as_str().unwrap();
+
+        let decoded = run_args(&["decode", lnurl]).unwrap();
+        assert_eq!(decoded["url"], url);
+        assert_eq!(decoded["is_lnurl_auth"], false);
+        assert_eq!(decoded["lightning_address"], Value::Null);
+
+        let decoded = run_args(&["decode", &lnurl.to_uppercase()]).unwrap();
+        assert_eq!(decoded["url"], url);
+
+        let address = "https://example.com/.well-known/lnurlp/alice";
+        let encoded = run_args(&["encode", address]).unwrap();
+        let decoded = run_args(&["decode", encoded["lnurl"].as_str().unwrap()]).unwrap();
+        assert_eq!(decoded["lightning_address"], "alice@example.com");
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        let err = run_args(&["decode", "lnurl1invalid"]).unwrap_err();
+        assert_eq!(exit_code(&err), 3);
+        let err = run_args(&["encode", "not a url"]).unwrap_err();
+        assert_eq!(exit_code(&err), 3);
+        let err = run_args(&["withdraw", "not a target", "lnbc1"]).unwrap_err();
+        assert_eq!(exit_code(&err), 3);
+    }
+
+    #[test]
+    fn test_withdraw_lnurl() {
+        let url = "https://example.com/withdraw?id=1";
+        let lnurl = LnUrl::from_url(url.to_string()).unwrap();
+        let encoded = lnurl.encode().unwrap();
+        assert_eq!(withdraw_lnurl(&encoded).unwrap(), lnurl);
+        assert_eq!(
+            withdraw_lnurl(&format!("LIGHTNING:{}", encoded.to_uppercase())).unwrap(),
+            lnurl
+        );
+        assert_eq!(withdraw_lnurl(url).unwrap(), lnurl);
+        assert_eq!(
+            withdraw_lnurl("alice@example.com").unwrap().as_str(),
+            "https://example.com/.well-known/lnurlp/alice"
+        );
+        assert!(withdraw_lnurl("ftp://example.com").is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "testing")]
+    fn test_exit_codes() {
+        use lnurl::testing::{Misbehaviour, MockLnUrlServer};
+
+        let server = MockLnUrlServer::start().unwrap();
+        server.add_pay_request("/lnurlp/ben", 1_000, 100_000_000, "Pay to ben");
+        server.add_withdraw_request("/withdraw", 100_000);
+        let lnurl = server.add_auth("/auth").to_string();
+        let pay_url = server.url("/lnurlp/ben");
+        let withdraw_url = server.url("/withdraw");
+        let client = Builder::default().build_blocking().unwrap();
+        let seed = "00".repeat(32);
+
+        let invoice = run_args(&["invoice", &pay_url, "10000"]).unwrap();
+        assert_eq!(invoice["amount_msats"], 10_000);
+        assert_eq!(
+            auth(&client, &lnurl, &seed).unwrap(),
+            json!({ "status": "OK" })
+        );
+        assert_eq!(
+            run_args(&["auth", &lnurl, "--seed", &seed]).unwrap(),
+            json!({ "status": "OK" })
+        );
+
+        let err = run_args(&["invoice", &pay_url, "1"]).unwrap_err();
+        assert_eq!(exit_code(&err), 3);
+
+        server.set_misbehaviour(Some(Misbehaviour::WrongAmount));
+        let err = run_args(&["invoice", &pay_url, "10000"]).unwrap_err();
+        assert_eq!(exit_code(&err), 5);
+
+        server.set_misbehaviour(Some(Misbehaviour::ErrorResponse("nope".to_string())));
+        let err = auth(&client, &lnurl, &seed).unwrap_err();
+        assert!(matches!(err, Error::Service(ref reason) if reason == "nope"));
+        assert_eq!(exit_code(&err), 6);
+        let err = run_args(&["withdraw", &withdraw_url, "lnbc1"]).unwrap_err();
+        assert_eq!(exit_code(&err), 6);
+        let err = run_args(&["invoice", &pay_url, "10000"]).unwrap_err();
+        assert_eq!(exit_code(&err), 6);
+        let err = run_args(&["resolve", &pay_url]).unwrap_err();
+        assert_eq!(exit_code(&err), 6);
+
+        server.set_misbehaviour(Some(Misbehaviour::HttpStatus(503)));
+        let err = run_args(&["resolve", &pay_url]).unwrap_err();
+        assert_eq!(exit_code(&err), 4);
+    }
+}
@@ -0,0 +1,287 @@
+//! BIP-353 DNS payment instructions for `₿user@domain` human-readable names.
+
+use crate::lightning_address::LightningAddress;
+use crate::pay::PayResponse;
+use crate::Error;
+#[cfg(any(feature = "async", feature = "async-https"))]
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Prefix of the TXT records holding BIP-353 payment instructions
+const BITCOIN_URI_SCHEME: &str = "bitcoin:";
+
+/// Resolves DNS TXT records for BIP-353 lookups.
+///
+/// Implementations are responsible for DNSSEC validation, as required by BIP-353.
+/// A name without TXT records (including a non-existent name) should resolve to an
+/// empty list rather than an error, so callers can fall back to LUD-16.
+/// Lookups block the calling thread, [`crate::AsyncClient`] takes an `AsyncDnsResolver`.
+pub trait DnsResolver {
+    /// Return the TXT records of `name`, with the character-strings of each record concatenated.
+    fn resolve_txt(&self, name: &str) -> Result<Vec<String>, Error>;
+}
+
+/// A [`DnsResolver`] that doesn't block, used by [`crate::AsyncClient`] so that lookups
+/// don't stall the executor.
+#[cfg(any(feature = "async", feature = "async-https"))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+pub trait AsyncDnsResolver {
+    /// Return the TXT records of `name`, see [`DnsResolver::resolve_txt`]
+    async fn resolve_txt(&self, name: &str) -> Result<Vec<String>, Error>;
+}
+
+/// A [`DnsResolver`] answering from a fixed set of records, for tests.
+#[derive(Debug, Clone, Default)]
+pub struct StaticDnsResolver {
+    records: HashMap<String, Vec<String>>,
+}
+
+impl StaticDnsResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a TXT record for `name`
+    pub fn insert(&mut self, name: &str, record: &str) {
+        self.records
+            .entry(normalize_name(name))
+            .or_default()
+            .push(record.to_string());
+    }
+}
+
+impl DnsResolver for StaticDnsResolver {
+    fn resolve_txt(&self, name: &str) -> Result<Vec<String>, Error> {
+        Ok(self
+            .records
+            .get(&normalize_name(name))
+            .cloned()
+            .unwrap_or_default())
+    }
+}
+
+#[cfg(any(feature = "async", feature = "async-https"))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl AsyncDnsResolver for StaticDnsResolver {
+    async fn resolve_txt(&self, name: &str) -> Result<Vec<String>, Error> {
+        DnsResolver::resolve_txt(self, name)
+    }
+}
+
+fn normalize_name(name: &str) -> String {
+    name.trim_end_matches('.').to_lowercase()
+}
+
+/// Parse a human-readable name, with or without the leading `₿`.
+pub fn parse_human_readable_name(name: &str) -> Result<LightningAddress, Error> {
+    LightningAddress::new(name.strip_prefix('₿').unwrap_or(name))
+}
+
+/// The DNS name holding the BIP-353 payment instructions for `address`
+pub fn dns_name(address: &LightningAddress) -> String {
+    format!(
+        "{}.user._bitcoin-payment.{}.",
+        address.local_part(),
+        address.domain()
+    )
+}
+
+/// Look up the BIP-353 payment instructions for `address`.
+///
+/// Returns `Ok(None)` if the name has no `bitcoin:` TXT record, and an error if it has
+/// more than one, as BIP-353 requires.
+pub fn resolve_payment_instructions<D: DnsResolver>(
+    dns: &D,
+    address: &LightningAddress,
+) -> Result<Option<BitcoinUri>, Error> {
+    select_payment_instructions(dns.resolve_txt(&dns_name(address))?)
+}
+
+/// Pick the single `bitcoin:` URI out of the TXT records of a BIP-353 name
+pub(crate) fn select_payment_instructions(
+    records: Vec<String>,
+) -> Result<Option<BitcoinUri>, Error> {
+    let mut uris = records
+        .into_iter()
+        .filter(|record| has_bitcoin_scheme(record));
+
+    match (uris.next(), uris.next()) {
+        (None, _) => Ok(None),
+        (Some(uri), None) => BitcoinUri::from_str(&uri).map(Some),
+        (Some(_), Some(_)) => Err(Error::InvalidPaymentInstructions(
+            "multiple bitcoin: records".to_string(),
+        )),
+    }
+}
+
+fn has_bitcoin_scheme(s: &str) -> bool {
+    s.get(..BITCOIN_URI_SCHEME.len())
+        .map(|scheme| scheme.eq_ignore_ascii_case(BITCOIN_URI_SCHEME))
+        .unwrap_or(false)
+}
+
+/// A BIP-21 `bitcoin:` URI, as published in BIP-353 TXT records
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BitcoinUri {
+    /// The on-chain address, if any. BIP-353 URIs often only carry query parameters.
+    pub address: Option<String>,
+    /// The percent-decoded query parameters, in order
+    pub params: Vec<(String, String)>,
+    uri: String,
+}
+
+impl BitcoinUri {
+    /// The value of the first query parameter named `key`, case-insensitively
+    pub fn param(&self, key: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The BOLT11 invoice from the `lightning` parameter
+    pub fn lightning(&self) -> Option<&str> {
+        self.param("lightning")
+    }
+
+    /// The BOLT12 offer from the `lno` parameter
+    pub fn offer(&self) -> Option<&str> {
+        self.param("lno")
+    }
+
+    /// The silent payment address from the `sp` parameter
+    pub fn silent_payment_address(&self) -> Option<&str> {
+        self.param("sp")
+    }
+
+    /// The URI as it was published
+    pub fn as_str(&self) -> &str {
+        &self.uri
+    }
+}
+
+impl FromStr for BitcoinUri {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if !has_bitcoin_scheme(s) {
+            return Err(Error::InvalidPaymentInstructions(
+                "not a bitcoin: URI".to_string(),
+            ));
+        }
+
+        let rest = &s[BITCOIN_URI_SCHEME.len()..];
+        let (address, query) = match rest.split_once('?') {
+            Some((address, query)) => (address, query),
+            None => (rest, ""),
+        };
+        let params = url::form_urlencoded::parse(query.as_bytes())
+            .into_owned()
+            .collect();
+
+        Ok(BitcoinUri {
+            address: Some(address.to_string()).filter(|a| !a.is_empty()),
+            params,
+            uri: s.to_string(),
+        })
+    }
+}
+
+/// Where to send a payment for a human-readable name
+#[derive(Debug, Clone, PartialEq)]
+pub enum PaymentTarget {
+    /// BIP-353 payment instructions found in DNS
+    Bip353(BitcoinUri),
+    /// The LUD-16 pay request, used when there are no BIP-353 payment instructions
+    LnUrlPay(PayResponse),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const URI: &str = "bitcoin:?lno=lno1qsgqmqvgm96frzdg8m0gc6nzeqffvzsqzrxqy32afmr3jn9ggkwg3egfwch2hy0l6jut6vfd8vpsc3h89l6u3dm4q2d6nuamav3w27xvdmv3lpgklhg7l5teypqz9l53hj7zvuaenh34xqsz2sa967yzqkylfu9xtcd5ymcmfp32h083e805y7jfd236w9afhavqqvl8uyma7x77yun4ehe9pnhu2gekjguexmxpqjcr2j822xr7q34p078gzslf9wpwz5y57alxu99s0z2ql0kfqvwhzycqq45ehh58xnfpuek80hw6spvwrvttjrrq9pphh0dpydh06qqspp5uq4gpyt6n9mwexde44qv7lstzzq60nr40ff38u27un6y53aypmx0p4qruk2tf9mjwqlhxak4znvna5y&sp=sp1qqweplq6ylpfrzuq6hfznzmv28djsraupudz0s0dclyt8erh70pgwxqkz3ydtw&lightning=LNBC1P3XYZ";
+
+    fn address() -> LightningAddress {
+        parse_human_readable_name("₿matt@mattcorallo.com").unwrap()
+    }
+
+    #[test]
+    fn test_parse_human_readable_name() {
+        assert_eq!(
+            address(),
+            LightningAddress::new("matt@mattcorallo.com").unwrap()
+        );
+        assert!(parse_human_readable_name("₿invalid").is_err());
+    }
+
+    #[test]
+    fn test_dns_name() {
+        assert_eq!(
+            dns_name(&address()),
+            "matt.user._bitcoin-payment.mattcorallo.com."
+        );
+    }
+
+    #[test]
+    fn test_parse_bitcoin_uri() {
+        let uri = BitcoinUri::from_str(URI).unwrap();
+        assert_eq!(uri.address, None);
+        assert!(uri.offer().unwrap().starts_with("lno1"));
+        assert!(uri.silent_payment_address().unwrap().starts_with("sp1"));
+        assert_eq!(uri.lightning(), Some("LNBC1P3XYZ"));
+        assert_eq!(uri.as_str(), URI);
+
+        let uri =
+            BitcoinUri::from_str("BITCOIN:bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq?label=a%20b")
+                .unwrap();
+        assert_eq!(
+            uri.address.as_deref(),
+            Some("bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq")
+        );
+        assert_eq!(uri.param("label"), Some("a b"));
+
+        assert!(BitcoinUri::from_str("lightning:lnbc1").is_err());
+    }
+
+    #[test]
+    fn test_resolve_payment_instructions() {
+        let mut dns = StaticDnsResolver::new();
+        dns.insert("matt.user._bitcoin-payment.mattcorallo.com", URI);
+        dns.insert("matt.user._bitcoin-payment.mattcorallo.com", "v=spf1 -all");
+
+        let uri = resolve_payment_instructions(&dns, &address())
+            .unwrap()
+            .unwrap();
+        assert_eq!(uri.as_str(), URI);
+
+        let other = LightningAddress::new("ben@benthecarman.com").unwrap();
+        assert_eq!(resolve_payment_instructions(&dns, &other).unwrap(), None);
+
+        dns.insert("matt.user._bitcoin-payment.mattcorallo.com.", URI);
+        assert!(matches!(
+            resolve_payment_instructions(&dns, &address()),
+            Err(Error::InvalidPaymentInstructions(_))
+        ));
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_async_resolve_human_readable_name() {
+        let mut dns = StaticDnsResolver::new();
+        dns.insert("matt.user._bitcoin-payment.mattcorallo.com", URI);
+        let client = crate::Builder::default().build_async().unwrap();
+
+        match client
+            .resolve_human_readable_name(&dns, "₿matt@mattcorallo.com")
+            .await
+            .unwrap()
+        {
+            PaymentTarget::Bip353(uri) => assert_eq!(uri.as_str(), URI),
+            other => panic!("expected BIP-353 instructions, got {:?}", other),
+        }
+    }
+}
@@ -3,19 +3,54 @@
 
 use bitcoin::secp256k1::ecdsa::Signature;
 use bitcoin::secp256k1::PublicKey;
+use bitcoin::Network;
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use ureq::{Agent, Proxy};
+use url::form_urlencoded::byte_serialize;
+use url::Url;
 
+use crate::bip353::{
+    parse_human_readable_name, resolve_payment_instructions, DnsResolver, PaymentTarget,
+};
+use crate::cache::{is_cacheable_url, store, ResponseCache};
 use crate::channel::ChannelResponse;
+use crate::keysend::KeysendResponse;
+use crate::lightning_address::LightningAddress;
 use crate::lnurl::LnUrl;
-use crate::pay::{LnURLPayInvoice, PayResponse};
+use crate::observer::RequestObserver;
+use crate::pay::{
+    LnURLPayInvoice, PayOptions, PayResponse, PayTarget, PreparedPayment, VerifyResponse,
+};
+use crate::retry::{host_of, is_transient, retry_after, CircuitBreaker, RequestKind, RetryPolicy};
+use crate::tor::TorConfig;
 use crate::withdraw::WithdrawalResponse;
-use crate::{decode_ln_url_response_from_json, Builder, Error, LnUrlResponse, Response};
+use crate::{
+    apply_resolve_overrides, decode_ln_url_response, parse_resolve_overrides, Builder, Error,
+    LnUrlResponse, Response, Tag, DEFAULT_MAX_BODY_SIZE, DEFAULT_MIN_INVOICE_EXPIRY,
+};
+use serde::de::DeserializeOwned;
 
 #[derive(Debug, Clone)]
 pub struct BlockingClient {
     agent: Agent,
+    resolve_overrides: HashMap<String, Url>,
+    retry: Option<RetryPolicy>,
+    circuit_breaker: Option<CircuitBreaker>,
+    cache: Option<Arc<dyn ResponseCache>>,
+    max_body_size: usize,
+    timeout: Option<Duration>,
+    tor: Option<TorConfig>,
+    /// Agents for requests routed through Tor, by proxy url
+    tor_agents: Arc<Mutex<HashMap<String, Agent>>>,
+    /// Headers sent with every request, including the user agent
+    headers: Vec<(String, String)>,
+    observer: Option<Arc<dyn RequestObserver>>,
+    network: Option<Network>,
+    min_invoice_expiry: u64,
 }
 
 impl BlockingClient {
@@ -31,24 +66,254 @@ impl BlockingClient {
             agent_builder = agent_builder.proxy(Proxy::new(proxy).unwrap());
         }
 
-        Ok(Self::from_agent(agent_builder.build()))
+        let mut client = Self::from_agent(agent_builder.build());
+        client.resolve_overrides = parse_resolve_overrides(&builder.resolve_overrides)?;
+        client.retry = builder.retry;
+        client.circuit_breaker = builder.circuit_breaker.map(CircuitBreaker::new);
+        client.cache = builder.cache;
+        client.max_body_size = builder.max_body_size.unwrap_or(DEFAULT_MAX_BODY_SIZE);
+        client.timeout = builder.timeout.map(Duration::from_secs);
+        if let Some(tor) = &builder.tor {
+            tor.validate()?;
+        }
+        client.tor = builder.tor;
+        client.headers = builder.headers.into_iter().collect();
+        if let Some(user_agent) = builder.user_agent {
+            client.headers.push(("User-Agent".to_string(), user_agent));
+        }
+        client.observer = builder.observer;
+        client.network = builder.network;
+        client.min_invoice_expiry = builder
+            .min_invoice_expiry
+            .unwrap_or(DEFAULT_MIN_INVOICE_EXPIRY);
+
+        Ok(client)
     }
 
     /// build a blocking client from an [`Agent`]
     pub fn from_agent(agent: Agent) -> Self {
-        BlockingClient { agent }
+        BlockingClient {
+            agent,
+            resolve_overrides: HashMap::new(),
+            retry: None,
+            circuit_breaker: None,
+            cache: None,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            timeout: None,
+            tor: None,
+            tor_agents: Arc::new(Mutex::new(HashMap::new())),
+            headers: Vec::new(),
+            observer: None,
+            network: None,
+            min_invoice_expiry: DEFAULT_MIN_INVOICE_EXPIRY,
+        }
     }
 
-    pub fn make_request(&self, url: &str) -> Result<LnUrlResponse, Error> {
-        let resp = self.agent.get(url).call();
+    /// Apply the builder's resolve overrides to `url`
+    fn resolve_url(&self, url: &str) -> String {
+        apply_resolve_overrides(&self.resolve_overrides, url)
+    }
+
+    /// The agent to send a request for `url` with, through Tor if the routing rules say so
+    fn agent_for(&self, url: &str) -> Result<Agent, Error> {
+        let proxy = match self
+            .tor
+            .as_ref()
+            .and_then(|tor| tor.proxy_for(url, "socks5"))
+        {
+            Some(proxy) => proxy,
+            None => return Ok(self.agent.clone()),
+        };
+
+        let mut agents = self.tor_agents.lock().unwrap();
+        if let Some(agent) = agents.get(&proxy) {
+            return Ok(agent.clone());
+        }
+
+        let mut agent_builder =
+            ureq::AgentBuilder::new().proxy(Proxy::new(&proxy).map_err(Error::Ureq)?);
+        if let Some(timeout) = self.timeout {
+            // the SOCKS connection is only bounded by the connect timeout
+            agent_builder = agent_builder.timeout(timeout).timeout_connect(timeout);
+        }
+        let agent = agent_builder.build();
+        agents.insert(proxy, agent.clone());
+
+        Ok(agent)
+    }
+
+    /// Send a GET request to `url` with extra `headers`, retrying it per the retry policy
+    /// if `kind` allows it
+    fn get(
+        &self,
+        url: &str,
+        kind: RequestKind,
+        headers: &[(&str, &str)],
+    ) -> Result<ureq::Response, Error> {
+        let url = self.resolve_url(url);
+        let host = host_of(&url);
+        let agent = self.agent_for(&url)?;
+
+        let mut retries = 0;
+        loop {
+            if let Some(breaker) = &self.circuit_breaker {
+                breaker.check(&host)?;
+            }
+
+            let mut request = agent.get(&url);
+            for (name, value) in &self.headers {
+                request = request.set(name, value);
+            }
+            for (name, value) in headers {
+                request = request.set(name, value);
+            }
+
+            if let Some(observer) = &self.observer {
+                observer.on_request(&url, retries);
+            }
+            let mut delay_hint = None;
+            let result = match request.call() {
+                Ok(resp) => Ok(resp),
+                Err(ureq::Error::Status(code, resp)) => {
+                    delay_hint = retry_after(code, resp.header("Retry-After"));
+                    Err(Error::HttpResponse(code))
+                }
+                Err(e) => Err(Error::Ureq(e)),
+            };
+            if let Some(observer) = &self.observer {
+                observer.on_response(&url, result.as_ref().map(|resp| resp.status()));
+            }
 
-        match resp {
-            Ok(resp) => {
-                let json: serde_json::Value = resp.into_json()?;
-                decode_ln_url_response_from_json(json)
+            if let Some(breaker) = &self.circuit_breaker {
+                breaker.record(&host, result.as_ref().is_err_and(is_transient));
             }
-            Err(ureq::Error::Status(code, _)) => Err(Error::HttpResponse(code)),
-            Err(e) => Err(Error::Ureq(e)),
+
+            let delay = match (&result, &self.retry) {
+                (Err(e), Some(policy)) if policy.should_retry(kind, retries, e) => {
+                    policy.delay(retries, delay_hint)
+                }
+                _ => None,
+            };
+            match delay {
+                Some(delay) => {
+                    std::thread::sleep(delay);
+                    retries += 1;
+                }
+                None => return result,
+            }
+        }
+    }
+
+    /// Read the body of `resp`, failing if it is larger than the maximum body size
+    fn read_body(&self, resp: ureq::Response) -> Result<String, Error> {
+        let mut body = String::new();
+        resp.into_reader()
+            .take(self.max_body_size as u64 + 1)
+            .read_to_string(&mut body)?;
+
+        if body.len() > self.max_body_size {
+            return Err(Error::ResponseTooLarge {
+                limit: self.max_body_size,
+            });
+        }
+
+        Ok(body)
+    }
+
+    /// Read the body of `resp` as JSON, failing if it is larger than the maximum body size
+    fn read_json<T: DeserializeOwned>(&self, resp: ureq::Response) -> Result<T, Error> {
+        Ok(serde_json::from_str(&self.read_body(resp)?)?)
+    }
+
+    pub fn make_request(&self, url: &str) -> Result<LnUrlResponse, Error> {
+        let cache = self.cache.as_deref().filter(|_| is_cacheable_url(url));
+        let cached = cache.and_then(|cache| cache.get(url));
+        if let Some(cached) = cached.as_ref().filter(|cached| cached.is_fresh()) {
+            return decode_ln_url_response(&cached.body);
+        }
+
+        let etag = cached.as_ref().and_then(|cached| cached.etag.clone());
+        let headers: Vec<_> = etag.iter().map(|e| ("If-None-Match", e.as_str())).collect();
+        let resp = self.get(url, RequestKind::Idempotent, &headers)?;
+
+        let cache_control = resp.header("Cache-Control").map(str::to_string);
+        let new_etag = resp.header("ETag").map(str::to_string);
+        // the previous etag only still applies if the server confirmed the cached body
+        let (body, etag) = match cached {
+            Some(cached) if resp.status() == 304 => (cached.body, new_etag.or(etag)),
+            _ => (self.read_body(resp)?, new_etag),
+        };
+
+        let response = decode_ln_url_response(&body)?;
+        if let Some(cache) = cache {
+            store(
+                cache,
+                url,
+                &response,
+                body,
+                cache_control.as_deref(),
+                etag.as_deref(),
+            );
+        }
+
+        Ok(response)
+    }
+
+    /// Resolve a `₿user@domain` human-readable name into a payment target.
+    ///
+    /// BIP-353 payment instructions are looked up with `dns` first, falling back to the
+    /// LUD-16 pay request of the equivalent lightning address if there are none, see
+    /// [`Self::resolve_lightning_address`].
+    pub fn resolve_human_readable_name<D: DnsResolver>(
+        &self,
+        dns: &D,
+        name: &str,
+    ) -> Result<PaymentTarget, Error> {
+        let address = parse_human_readable_name(name)?;
+        if let Some(uri) = resolve_payment_instructions(dns, &address)? {
+            return Ok(PaymentTarget::Bip353(uri));
+        }
+
+        let pay = self.resolve_lightning_address(&address)?;
+        Ok(PaymentTarget::LnUrlPay(pay))
+    }
+
+    /// Fetch the pay request of a lightning address, checking that its metadata identifies
+    /// the address as LUD-16 requires.
+    ///
+    /// Returns [`Error::IdentifierMismatch`] if the metadata has no `text/identifier` or
+    /// `text/email` entry equal to `address`.
+    pub fn resolve_lightning_address(
+        &self,
+        address: &LightningAddress,
+    ) -> Result<PayResponse, Error> {
+        let pay = self.pay_response(&address.lnurlp_url())?;
+        pay.verify_identifier(address)?;
+        Ok(pay)
+    }
+
+    /// Fetch the keysend details of a lightning address, for wallets that prefer keysend
+    /// payments where they are offered.
+    ///
+    /// Returns [`Error::WrongTag`] if the service serves something else at the keysend url.
+    pub fn resolve_keysend(&self, address: &LightningAddress) -> Result<KeysendResponse, Error> {
+        match self.make_request(&address.keysend_url())? {
+            LnUrlResponse::LnUrlKeysendResponse(keysend) => Ok(keysend),
+            other => Err(Error::WrongTag {
+                expected: Tag::Keysend,
+                found: other.tag(),
+            }),
+        }
+    }
+
+    /// Fetch the pay request at `url`, failing with [`Error::WrongTag`] for other requests
+    fn pay_response(&self, url: &str) -> Result<PayResponse, Error> {
+        match self.make_request(url)? {
+            LnUrlResponse::LnUrlPayResponse(pay) => Ok(pay),
+            other => Err(Error::WrongTag {
+                expected: Tag::PayRequest,
+                found: other.tag(),
+            }),
         }
     }
 
@@ -60,9 +325,7 @@ impl BlockingClient {
         comment: Option<&str>,
     ) -> Result<LnURLPayInvoice, Error> {
         // verify amount
-        if msats < pay.min_sendable || msats > pay.max_sendable {
-            return Err(Error::InvalidAmount);
-        }
+        pay.check_amount(msats)?;
 
         // verify comment length
         if let Some(comment) = comment {
@@ -75,43 +338,85 @@ impl BlockingClient {
 
         let symbol = if pay.callback.contains('?') { "&" } else { "?" };
 
+        let encode = |value: &str| byte_serialize(value.as_bytes()).collect::<String>();
         let url = match (zap_request, comment) {
             (Some(_), Some(_)) => return Err(Error::InvalidComment),
             (Some(zap_request), None) => format!(
                 "{}{}amount={}&nostr={}",
-                pay.callback, symbol, msats, zap_request
+                pay.callback,
+                symbol,
+                msats,
+                encode(&zap_request)
             ),
             (None, Some(comment)) => format!(
                 "{}{}amount={}&comment={}",
-                pay.callback, symbol, msats, comment
+                pay.callback,
+                symbol,
+                msats,
+                encode(comment)
             ),
             (None, None) => format!("{}{}amount={}", pay.callback, symbol, msats),
         };
 
-        let resp = self.agent.get(&url).call();
-
-        match resp {
-            Ok(resp) => {
-                let json: serde_json::Value = resp.into_json()?;
-                let result = serde_json::from_value::<LnURLPayInvoice>(json.clone());
-
-                match result {
-                    Ok(invoice) => {
-                        // verify the returned invoice's amount matches the requested amount (LUD-06)
-                        invoice.verify_amount(msats)?;
-                        Ok(invoice)
-                    }
-                    Err(_) => {
-                        let response = serde_json::from_value::<Response<()>>(json)?;
-                        match response {
-                            Response::Error { reason } => Err(Error::Other(reason)),
-                            Response::Ok { .. } => unreachable!("Ok response should be an invoice"),
-                        }
-                    }
+        let resp = self.get(&url, RequestKind::Invoice, &[])?;
+
+        let json: serde_json::Value = self.read_json(resp)?;
+        let result = serde_json::from_value::<LnURLPayInvoice>(json.clone());
+
+        match result {
+            Ok(invoice) => {
+                // verify the returned invoice's amount matches the requested amount (LUD-06)
+                invoice.verify_amount(msats)?;
+                if let Some(network) = self.network {
+                    invoice.verify_network(network)?;
+                }
+                invoice.verify_expiry(self.min_invoice_expiry)?;
+                Ok(invoice)
+            }
+            Err(_) => {
+                let response = serde_json::from_value::<Response<()>>(json)?;
+                match response {
+                    Response::Error { reason } => Err(Error::Service(reason)),
+                    Response::Ok { .. } => Err(Error::InvalidResponse),
                 }
             }
-            Err(ureq::Error::Status(code, _)) => Err(Error::HttpResponse(code)),
-            Err(e) => Err(Error::Ureq(e)),
+        }
+    }
+
+    /// Fetch a verified invoice for `msats` from an LNURL, lightning address or pay request url.
+    ///
+    /// Returns [`Error::WrongTag`] if the target is not an LNURL-pay request,
+    /// [`Error::IdentifierMismatch`] if a lightning address is not identified by its pay
+    /// request and [`Error::AmountOutOfRange`] if `msats` is not accepted by the service.
+    pub fn pay_request(
+        &self,
+        target: impl Into<PayTarget>,
+        msats: u64,
+        options: PayOptions,
+    ) -> Result<PreparedPayment, Error> {
+        let pay = match target.into() {
+            PayTarget::LightningAddress(address) => self.resolve_lightning_address(&address)?,
+            target => self.pay_response(&target.url())?,
+        };
+        pay.check_amount(msats)?;
+
+        let invoice = self.get_invoice(
+            &pay,
+            msats,
+            options.zap_request.clone(),
+            options.comment.as_deref(),
+        )?;
+
+        PreparedPayment::new(pay, invoice, msats, options.zap_request.as_deref())
+    }
+
+    pub fn verify(&self, url: &str) -> Result<VerifyResponse, Error> {
+        let resp = self.get(url, RequestKind::Idempotent, &[])?;
+
+        let rsp: Response<VerifyResponse> = self.read_json(resp)?;
+        match rsp {
+            Response::Error { reason } => Err(Error::Service(reason)),
+            Response::Ok(r) => Ok(r),
         }
     }
 
@@ -131,13 +436,9 @@ impl BlockingClient {
             withdrawal.callback, symbol, withdrawal.k1, invoice
         );
 
-        let resp = self.agent.get(&url).call();
+        let resp = self.get(&url, RequestKind::Once, &[])?;
 
-        match resp {
-            Ok(resp) => Ok(resp.into_json()?),
-            Err(ureq::Error::Status(code, _)) => Err(Error::HttpResponse(code)),
-            Err(e) => Err(Error::Ureq(e)),
-        }
+        self.read_json(resp)
     }
 
     pub fn open_channel(
@@ -161,13 +462,9 @@ impl BlockingClient {
             private as i32 // 0 or 1
         );
 
-        let resp = self.agent.get(&url).call();
+        let resp = self.get(&url, RequestKind::Once, &[])?;
 
-        match resp {
-            Ok(resp) => Ok(resp.into_json()?),
-            Err(ureq::Error::Status(code, _)) => Err(Error::HttpResponse(code)),
-            Err(e) => Err(Error::Ureq(e)),
-        }
+        self.read_json(resp)
     }
 
     pub fn lnurl_auth(
@@ -176,14 +473,10 @@ impl BlockingClient {
         sig: Signature,
         key: PublicKey,
     ) -> Result<Response<()>, Error> {
-        let url = format!("{}&sig={}&key={}", lnurl.url, sig, key);
+        let url = format!("{}&sig={}&key={}", lnurl.as_str(), sig, key);
 
-        let resp = self.agent.get(&url).call();
+        let resp = self.get(&url, RequestKind::Once, &[])?;
 
-        match resp {
-            Ok(resp) => Ok(resp.into_json()?),
-            Err(ureq::Error::Status(code, _)) => Err(Error::HttpResponse(code)),
-            Err(e) => Err(Error::Ureq(e)),
-        }
+        self.read_json(resp)
     }
 }
@@ -0,0 +1,175 @@
+//! Minimal BOLT11 invoice encoding, used to mint real signed invoices in tests.
+
+use super::{currency_prefix, fes_to_bytes_padded};
+use bech32::primitives::encode::Encoder;
+use bech32::{Bech32, ByteIterExt, Fe32, Hrp};
+use bitcoin::hashes::sha256;
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::{Message, Secp256k1, SecretKey};
+use bitcoin::Network;
+use std::convert::TryFrom;
+
+/// The description committed to by an invoice
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Description<'a> {
+    /// A `d` field holding the description itself
+    Direct(&'a str),
+    /// A `h` field holding the SHA256 of the description
+    Hash(sha256::Hash),
+}
+
+/// The fields of an invoice to encode and sign
+#[derive(Debug, Clone)]
+pub(crate) struct UnsignedInvoice<'a> {
+    pub network: Network,
+    pub amount_msats: Option<u64>,
+    pub timestamp: u64,
+    pub payment_hash: sha256::Hash,
+    pub payment_secret: [u8; 32],
+    pub description: Description<'a>,
+    pub expiry_secs: Option<u64>,
+    pub min_final_cltv_expiry_delta: u64,
+}
+
+/// The BOLT11 HRP amount for `msats`, using the largest multiplier that represents it exactly
+// `u64::is_multiple_of` is too new for this crate's MSRV.
+#[allow(clippy::manual_is_multiple_of)]
+fn encode_amount(msats: u64) -> String {
+    const MULTIPLIERS: [(u64, &str); 4] = [
+        (100_000_000_000, ""),
+        (100_000_000, "m"),
+        (100_000, "u"),
+        (100, "n"),
+    ];
+
+    MULTIPLIERS
+        .iter()
+        .find(|(unit, _)| msats % unit == 0)
+        .map(|(unit, suffix)| format!("{}{}", msats / unit, suffix))
+        .unwrap_or_else(|| format!("{}p", u128::from(msats) * 10))
+}
+
+fn fe(value: u64) -> Fe32 {
+    Fe32::try_from((value & 31) as u8).expect("less than 32")
+}
+
+/// Big-endian base32 encoding of `value`, without leading zeros
+fn encode_int(mut value: u64) -> Vec<Fe32> {
+    let mut out = vec![];
+    while value > 0 {
+        out.push(fe(value));
+        value >>= 5;
+    }
+    out.reverse();
+    out
+}
+
+/// Append a tagged field, whose length is encoded as two big-endian base32 digits
+fn push_field(data: &mut Vec<Fe32>, tag: char, value: Vec<Fe32>) {
+    let len = value.len() as u64;
+    data.push(Fe32::from_char(tag).expect("valid tag"));
+    data.push(fe(len >> 5));
+    data.push(fe(len));
+    data.extend(value);
+}
+
+fn bytes_to_fes(bytes: &[u8]) -> Vec<Fe32> {
+    bytes.iter().copied().bytes_to_fes().collect()
+}
+
+impl UnsignedInvoice<'_> {
+    /// Encode the invoice, signing it with `node_key`
+    pub fn sign(&self, node_key: &SecretKey) -> String {
+        let amount = self.amount_msats.map(encode_amount).unwrap_or_default();
+        let hrp = format!("ln{}{}", currency_prefix(self.network), amount);
+
+        let mut data = encode_int(self.timestamp);
+        while data.len() < 7 {
+            data.insert(0, Fe32::Q);
+        }
+
+        push_field(
+            &mut data,
+            'p',
+            bytes_to_fes(self.payment_hash.as_byte_array()),
+        );
+        push_field(&mut data, 's', bytes_to_fes(&self.payment_secret));
+        match self.description {
+            Description::Direct(description) => {
+                push_field(&mut data, 'd', bytes_to_fes(description.as_bytes()))
+            }
+            Description::Hash(hash) => {
+                push_field(&mut data, 'h', bytes_to_fes(hash.as_byte_array()))
+            }
+        }
+        if let Some(expiry) = self.expiry_secs {
+            push_field(&mut data, 'x', encode_int(expiry));
+        }
+        push_field(&mut data, 'c', encode_int(self.min_final_cltv_expiry_delta));
+        // var_onion_optin (bit 8) and payment_secret (bit 14), both required
+        push_field(&mut data, '9', encode_int((1 << 8) | (1 << 14)));
+
+        let mut preimage = hrp.as_bytes().to_vec();
+        preimage.extend(fes_to_bytes_padded(&data));
+        let msg = Message::from_digest(sha256::Hash::hash(&preimage).to_byte_array());
+        let (recovery_id, signature) = Secp256k1::signing_only()
+            .sign_ecdsa_recoverable(&msg, node_key)
+            .serialize_compact();
+
+        let mut sig_bytes = signature.to_vec();
+        sig_bytes.push(recovery_id.to_i32() as u8);
+        data.extend(bytes_to_fes(&sig_bytes));
+
+        let hrp = Hrp::parse(&hrp).expect("valid hrp");
+        Encoder::<_, Bech32>::new(data.into_iter(), &hrp)
+            .chars()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{parse_amount_msats, parse_expiry, DecodedInvoice};
+    use super::*;
+    use bech32::primitives::decode::UncheckedHrpstring;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_encode_amount() {
+        assert_eq!(encode_amount(200_000_000_000), "2");
+        assert_eq!(encode_amount(2_000_000_000), "20m");
+        assert_eq!(encode_amount(250_000_000), "2500u");
+        assert_eq!(encode_amount(1_000), "10n");
+        assert_eq!(encode_amount(967_878_534), "9678785340p");
+    }
+
+    #[test]
+    fn test_sign_invoice() {
+        let invoice = UnsignedInvoice {
+            network: Network::Regtest,
+            amount_msats: Some(1_000),
+            timestamp: 1_700_000_000,
+            payment_hash: sha256::Hash::hash(&[1; 32]),
+            payment_secret: [2; 32],
+            description: Description::Direct("test"),
+            expiry_secs: Some(3600),
+            min_final_cltv_expiry_delta: 18,
+        };
+        let key = SecretKey::from_slice(&[3; 32]).unwrap();
+        let encoded = invoice.sign(&key);
+
+        assert!(encoded.starts_with("lnbcrt10n1"));
+        let parsed = UncheckedHrpstring::new(&encoded).unwrap();
+        assert!(parsed.validate_checksum::<Bech32>().is_ok());
+        assert_eq!(parse_amount_msats(&encoded).unwrap(), Some(1_000));
+        assert_eq!(parse_expiry(&encoded).unwrap(), (1_700_000_000, 3600));
+
+        let decoded = DecodedInvoice::from_str(&encoded).unwrap();
+        assert_eq!(decoded.network(), Some(Network::Regtest));
+        assert_eq!(decoded.payment_hash, invoice.payment_hash);
+        assert_eq!(decoded.payment_secret, Some([2; 32]));
+        assert_eq!(decoded.description.as_deref(), Some("test"));
+        assert_eq!(decoded.payee, key.public_key(&Secp256k1::signing_only()));
+        assert_eq!(decoded.features.bits(), vec![8, 14]);
+    }
+}
@@ -0,0 +1,516 @@
+//! Decoding of BOLT11 invoices, without pulling in a full lightning implementation.
+//!
+//! [`DecodedInvoice`] decodes every tagged field of an invoice, verifying its checksum
+//! and signature. The client checks of the amount, network and expiry of invoices only
+//! read the fields they need.
+
+use crate::lnurl::LnUrlChecksum;
+use crate::Error;
+use bech32::primitives::decode::UncheckedHrpstring;
+use bech32::{Fe32, Fe32IterExt};
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::secp256k1::ecdsa::{RecoverableSignature, RecoveryId};
+use bitcoin::secp256k1::{Message, PublicKey, Secp256k1};
+use bitcoin::Network;
+use std::convert::{TryFrom, TryInto};
+use std::str::FromStr;
+
+#[cfg(any(test, feature = "mock"))]
+mod encode;
+#[cfg(any(test, feature = "mock"))]
+pub(crate) use encode::{Description, UnsignedInvoice};
+
+/// Expiry of invoices without an `x` field, in seconds
+const DEFAULT_EXPIRY_SECS: u64 = 3600;
+
+/// `min_final_cltv_expiry_delta` of invoices without a `c` field
+const DEFAULT_MIN_FINAL_CLTV_EXPIRY_DELTA: u64 = 18;
+
+/// Length of the signature at the end of the data part, 65 bytes in 5 bit characters
+const SIGNATURE_LEN: usize = 104;
+
+/// A BOLT11 invoice, decoded and with a verified checksum and signature
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedInvoice {
+    /// Currency prefix, e.g. `bc` for mainnet, see [`DecodedInvoice::network`]
+    pub currency_prefix: String,
+    /// Amount in millisatoshis, `None` if the payer chooses the amount
+    pub amount_msats: Option<u64>,
+    /// Creation time, in seconds since the UNIX epoch
+    pub timestamp: u64,
+    /// Hash of the preimage revealed by paying the invoice
+    pub payment_hash: sha256::Hash,
+    /// Secret to send to the payee with the payment
+    pub payment_secret: Option<[u8; 32]>,
+    /// Description of the payment, committed to in full
+    pub description: Option<String>,
+    /// SHA256 of the description of the payment, e.g. of the LNURL-pay metadata
+    pub description_hash: Option<sha256::Hash>,
+    /// Node to pay, from the `n` field or recovered from the signature
+    pub payee: PublicKey,
+    /// Seconds after [`DecodedInvoice::timestamp`] the invoice expires
+    pub expiry_secs: u64,
+    /// CLTV expiry delta of the last hop
+    pub min_final_cltv_expiry_delta: u64,
+    /// On-chain addresses to pay instead
+    pub fallbacks: Vec<Fallback>,
+    /// Private routes to the payee
+    pub route_hints: Vec<Vec<RouteHintHop>>,
+    /// Features of the invoice
+    pub features: Features,
+    /// Metadata to send to the payee with the payment
+    pub metadata: Option<Vec<u8>>,
+}
+
+/// An on-chain fallback address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fallback {
+    /// Witness version, or 17 for P2PKH and 18 for P2SH
+    pub version: u8,
+    /// Witness program, or the public key hash or script hash
+    pub program: Vec<u8>,
+}
+
+/// A hop of a private route to the payee
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteHintHop {
+    /// Node at the start of the channel
+    pub src_node_id: PublicKey,
+    /// Short channel id of the channel
+    pub short_channel_id: u64,
+    /// Base fee of the channel, in millisatoshis
+    pub fee_base_msat: u32,
+    /// Proportional fee of the channel, in millionths
+    pub fee_proportional_millionths: u32,
+    /// CLTV expiry delta of the channel
+    pub cltv_expiry_delta: u16,
+}
+
+/// Feature bits of an invoice
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Features {
+    /// The bits, big-endian in 5 bit characters
+    fes: Vec<u8>,
+}
+
+impl Features {
+    /// Whether feature bit `bit` is set
+    pub fn is_set(&self, bit: usize) -> bool {
+        let index = bit / 5;
+        index < self.fes.len() && (self.fes[self.fes.len() - 1 - index] >> (bit % 5)) & 1 == 1
+    }
+
+    /// The feature bits that are set, in increasing order
+    pub fn bits(&self) -> Vec<usize> {
+        (0..self.fes.len() * 5)
+            .filter(|bit| self.is_set(*bit))
+            .collect()
+    }
+}
+
+impl DecodedInvoice {
+    /// The network of the currency prefix, `None` if it is unknown
+    pub fn network(&self) -> Option<Network> {
+        [
+            Network::Bitcoin,
+            Network::Testnet,
+            Network::Signet,
+            Network::Regtest,
+        ]
+        .iter()
+        .copied()
+        .find(|network| currency_prefix(*network) == self.currency_prefix)
+    }
+
+    /// When the invoice expires, in seconds since the UNIX epoch
+    pub fn expires_at(&self) -> u64 {
+        self.timestamp.saturating_add(self.expiry_secs)
+    }
+}
+
+impl FromStr for DecodedInvoice {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parsed = UncheckedHrpstring::new(s).map_err(|e| invalid(&e.to_string()))?;
+        let hrp = parsed.hrp().as_str().to_ascii_lowercase();
+        let (currency_prefix, amount_msats) = parse_hrp(&hrp)?;
+
+        // invoices are not limited to the 1023 characters of bech32
+        let parsed = parsed
+            .validate_and_remove_checksum::<LnUrlChecksum>()
+            .map_err(|e| invalid(&e.to_string()))?;
+        let data = to_fes(parsed.data_part_ascii_no_checksum())?;
+        let fields_len = data
+            .len()
+            .checked_sub(SIGNATURE_LEN)
+            .filter(|len| *len >= 7)
+            .ok_or_else(|| invalid("too short"))?;
+        let (fields, signature) = data.split_at(fields_len);
+
+        let mut payment_hash = None;
+        let mut payment_secret = None;
+        let mut description = None;
+        let mut description_hash = None;
+        let mut payee = None;
+        let mut expiry_secs = DEFAULT_EXPIRY_SECS;
+        let mut min_final_cltv_expiry_delta = DEFAULT_MIN_FINAL_CLTV_EXPIRY_DELTA;
+        let mut fallbacks = vec![];
+        let mut route_hints = vec![];
+        let mut features = Features::default();
+        let mut metadata = None;
+
+        // fields of an unexpected length are skipped, as BOLT11 requires
+        for (tag, value) in tagged_fields(&fields[7..])? {
+            match (tag.to_char(), value.len()) {
+                ('p', 52) => payment_hash = Some(sha256::Hash::from_byte_array(to_array(value))),
+                ('s', 52) => payment_secret = Some(to_array(value)),
+                ('d', _) => {
+                    let text = String::from_utf8(to_bytes(value))
+                        .map_err(|_| invalid("description is not UTF-8"))?;
+                    description = Some(text);
+                }
+                ('h', 52) => {
+                    description_hash = Some(sha256::Hash::from_byte_array(to_array(value)))
+                }
+                ('n', 53) => {
+                    let node_id = PublicKey::from_slice(&to_bytes(value))
+                        .map_err(|_| invalid("invalid payee public key"))?;
+                    payee = Some(node_id);
+                }
+                ('x', _) => expiry_secs = read_int(value)?,
+                ('c', _) => min_final_cltv_expiry_delta = read_int(value)?,
+                ('f', len) if len > 0 => fallbacks.push(Fallback {
+                    version: value[0].to_u8(),
+                    program: to_bytes(&value[1..]),
+                }),
+                ('r', _) => route_hints.push(parse_route_hint(&to_bytes(value))?),
+                ('9', _) => {
+                    features = Features {
+                        fes: value.iter().map(|fe| fe.to_u8()).collect(),
+                    }
+                }
+                ('m', _) => metadata = Some(to_bytes(value)),
+                _ => {}
+            }
+        }
+
+        let signature = to_bytes(signature);
+        let recovery_id = RecoveryId::from_i32(i32::from(signature[64]))
+            .map_err(|_| invalid("invalid signature recovery id"))?;
+        let signature = RecoverableSignature::from_compact(&signature[..64], recovery_id)
+            .map_err(|_| invalid("invalid signature"))?;
+
+        let mut preimage = hrp.into_bytes();
+        preimage.extend(fes_to_bytes_padded(fields));
+        let msg = Message::from_digest(sha256::Hash::hash(&preimage).to_byte_array());
+
+        let secp = Secp256k1::verification_only();
+        let payee = match payee {
+            Some(payee) => secp
+                .verify_ecdsa(&msg, &signature.to_standard(), &payee)
+                .map(|_| payee),
+            None => secp.recover_ecdsa(&msg, &signature),
+        }
+        .map_err(|_| invalid("invalid signature"))?;
+
+        Ok(DecodedInvoice {
+            currency_prefix,
+            amount_msats,
+            timestamp: read_int(&fields[..7])?,
+            payment_hash: payment_hash.ok_or_else(|| invalid("missing payment hash"))?,
+            payment_secret,
+            description,
+            description_hash,
+            payee,
+            expiry_secs,
+            min_final_cltv_expiry_delta,
+            fallbacks,
+            route_hints,
+            features,
+            metadata,
+        })
+    }
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::InvalidInvoice(msg.to_string())
+}
+
+fn to_fes(ascii: &[u8]) -> Result<Vec<Fe32>, Error> {
+    ascii
+        .iter()
+        .map(|c| Fe32::from_char(char::from(*c)))
+        .collect::<Result<_, _>>()
+        .map_err(|e| invalid(&e.to_string()))
+}
+
+/// Pack 5 bit characters into bytes, dropping the padding bits
+fn to_bytes(fes: &[Fe32]) -> Vec<u8> {
+    fes.iter().copied().fes_to_bytes().collect()
+}
+
+/// The 32 bytes of a field of 52 characters
+fn to_array(fes: &[Fe32]) -> [u8; 32] {
+    to_bytes(fes)
+        .try_into()
+        .expect("52 characters hold 32 bytes")
+}
+
+/// Pack 5 bit characters into bytes, zero padding the last byte
+fn fes_to_bytes_padded(fes: &[Fe32]) -> Vec<u8> {
+    let mut bytes = vec![];
+    let mut acc = 0u32;
+    let mut bits = 0;
+    for fe in fes {
+        acc = (acc << 5) | u32::from(fe.to_u8());
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+    if bits > 0 {
+        bytes.push((acc << (8 - bits)) as u8);
+    }
+    bytes
+}
+
+/// Big-endian base32 integer, of at most 60 bits
+fn read_int(fes: &[Fe32]) -> Result<u64, Error> {
+    if fes.len() > 12 {
+        return Err(invalid("integer field too long"));
+    }
+
+    Ok(fes
+        .iter()
+        .fold(0u64, |acc, fe| (acc << 5) | u64::from(fe.to_u8())))
+}
+
+/// Split the tagged fields following the timestamp into their type and value
+fn tagged_fields(mut data: &[Fe32]) -> Result<Vec<(Fe32, &[Fe32])>, Error> {
+    let mut fields = vec![];
+    while !data.is_empty() {
+        if data.len() < 3 {
+            return Err(invalid("truncated tagged field"));
+        }
+        let len = (usize::from(data[1].to_u8()) << 5) | usize::from(data[2].to_u8());
+        let value = data
+            .get(3..3 + len)
+            .ok_or_else(|| invalid("truncated tagged field"))?;
+        fields.push((data[0], value));
+        data = &data[3 + len..];
+    }
+
+    Ok(fields)
+}
+
+/// Parse the hops of an `r` field, 51 bytes each
+fn parse_route_hint(bytes: &[u8]) -> Result<Vec<RouteHintHop>, Error> {
+    let hops = bytes.chunks_exact(51);
+    if bytes.is_empty() || !hops.remainder().is_empty() {
+        return Err(invalid("invalid route hint length"));
+    }
+
+    hops.map(|hop| {
+        Ok(RouteHintHop {
+            src_node_id: PublicKey::from_slice(&hop[..33])
+                .map_err(|_| invalid("invalid route hint public key"))?,
+            short_channel_id: u64::from_be_bytes(hop[33..41].try_into().expect("8 bytes")),
+            fee_base_msat: u32::from_be_bytes(hop[41..45].try_into().expect("4 bytes")),
+            fee_proportional_millionths: u32::from_be_bytes(
+                hop[45..49].try_into().expect("4 bytes"),
+            ),
+            cltv_expiry_delta: u16::from_be_bytes(hop[49..51].try_into().expect("2 bytes")),
+        })
+    })
+    .collect()
+}
+
+/// The currency prefix of BOLT11 invoices on `network`
+pub(crate) fn currency_prefix(network: Network) -> &'static str {
+    match network {
+        Network::Bitcoin => "bc",
+        Network::Testnet => "tb",
+        Network::Signet => "tbs",
+        Network::Regtest => "bcrt",
+        _ => "tb",
+    }
+}
+
+/// Parse the currency prefix of a BOLT11 invoice, e.g. `bc` for mainnet
+pub(crate) fn parse_currency_prefix(invoice: &str) -> Result<String, Error> {
+    let parsed = UncheckedHrpstring::new(invoice).map_err(|e| invalid(&e.to_string()))?;
+    let (prefix, _) = parse_hrp(&parsed.hrp().as_str().to_ascii_lowercase())?;
+    Ok(prefix)
+}
+
+/// Parse the creation time, in seconds since the UNIX epoch, and the expiry, in seconds,
+/// of a BOLT11 invoice from its timestamp and `x` tagged field.
+pub(crate) fn parse_expiry(invoice: &str) -> Result<(u64, u64), Error> {
+    let parsed = UncheckedHrpstring::new(invoice).map_err(|e| invalid(&e.to_string()))?;
+    let data = to_fes(parsed.data_part_ascii())?;
+
+    // the data ends with the signature and the 6 character checksum
+    let fields_len = data
+        .len()
+        .checked_sub(SIGNATURE_LEN + 6)
+        .filter(|len| *len >= 7)
+        .ok_or_else(|| invalid("too short"))?;
+    let fields = &data[..fields_len];
+
+    let mut expiry = DEFAULT_EXPIRY_SECS;
+    for (tag, value) in tagged_fields(&fields[7..])? {
+        if tag == Fe32::X {
+            expiry = read_int(value)?;
+        }
+    }
+
+    Ok((read_int(&fields[..7])?, expiry))
+}
+
+/// Parse the amount (in millisatoshis) encoded in a BOLT11 invoice without
+/// decoding the rest of the invoice.
+///
+/// The amount lives entirely in the human-readable part (HRP) of the invoice,
+/// so we only parse and validate the bech32 structure (via the `bech32` crate)
+/// and read the amount out of the HRP.
+///
+/// Returns `Ok(None)` for an amountless invoice, `Ok(Some(msats))` when an
+/// amount is present, and [`Error::InvalidInvoice`] if the HRP is malformed.
+pub(crate) fn parse_amount_msats(invoice: &str) -> Result<Option<u64>, Error> {
+    // Parse the bech32 structure to split off and validate the human-readable
+    // part. We deliberately do not verify the checksum: BOLT11 invoices are not
+    // length-bounded, the amount lives entirely in the HRP, and the invoice
+    // signature is the (possibly malicious) service's own, so a checksum check
+    // would add no protection against a mismatched amount.
+    let parsed = UncheckedHrpstring::new(invoice).map_err(|e| invalid(&e.to_string()))?;
+    // The HRP is all lower- or all upper-case (mixed case is rejected by the
+    // parser above); normalize so the `ln` prefix and multiplier letters match.
+    let (_, msats) = parse_hrp(&parsed.hrp().as_str().to_ascii_lowercase())?;
+    Ok(msats)
+}
+
+/// Split a lowercase HRP into its currency prefix and amount in millisatoshis.
+///
+/// The HRP is `ln` + a currency prefix (letters only) + an optional amount, where
+/// the amount is one or more digits followed by an optional multiplier letter
+/// (`m`/`u`/`n`/`p`).
+// `u128::is_multiple_of` is too new for this crate's MSRV.
+#[allow(clippy::manual_is_multiple_of)]
+fn parse_hrp(hrp: &str) -> Result<(String, Option<u64>), Error> {
+    let hrp = hrp
+        .strip_prefix("ln")
+        .ok_or_else(|| invalid("not a lightning invoice"))?;
+
+    // The currency prefix contains no digits, so the amount section (if any)
+    // starts at the first digit in the HRP.
+    let (prefix, amount) = match hrp.find(|c: char| c.is_ascii_digit()) {
+        None => return Ok((hrp.to_string(), None)), // amountless invoice
+        Some(idx) => hrp.split_at(idx),
+    };
+
+    // Split off an optional trailing multiplier letter.
+    let (digits, multiplier) = match amount.chars().last() {
+        Some(c) if c.is_ascii_digit() => (amount, None),
+        Some(c) => (&amount[..amount.len() - 1], Some(c)),
+        None => unreachable!("amount is non-empty"),
+    };
+
+    let value: u128 = digits
+        .parse()
+        .map_err(|_| invalid("invalid amount digits"))?;
+
+    // Convert to millisatoshis. 1 BTC = 100_000_000_000 msat, and the BOLT11
+    // multipliers scale the value by 10^-3 (m), 10^-6 (u), 10^-9 (n), 10^-12 (p)
+    // bitcoin respectively.
+    let msats: u128 = match multiplier {
+        None => value.checked_mul(100_000_000_000),
+        Some('m') => value.checked_mul(100_000_000),
+        Some('u') => value.checked_mul(100_000),
+        Some('n') => value.checked_mul(100),
+        Some('p') => {
+            // A pico-bitcoin amount must be a multiple of 10, as 1 msat is the
+            // smallest representable unit (10 pico-bitcoin).
+            if value % 10 != 0 {
+                return Err(invalid("sub-millisatoshi amount"));
+            }
+            Some(value / 10)
+        }
+        Some(_) => return Err(invalid("invalid amount multiplier")),
+    }
+    .ok_or_else(|| invalid("amount overflow"))?;
+
+    let msats = u64::try_from(msats).map_err(|_| invalid("amount overflow"))?;
+
+    Ok((prefix.to_string(), Some(msats)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // BOLT11 test vectors, signed by the node 03e7156ae33b0a208d0744199163177e909e80176e55d97a2f221ede0f934dd9ad
+    const PAYEE: &str = "03e7156ae33b0a208d0744199163177e909e80176e55d97a2f221ede0f934dd9ad";
+    // 9678785340p, with a route hint and an expiry of a week
+    const STORE: &str = "lnbc9678785340p1pwmna7lpp5gc3xfm08u9qy06djf8dfflhugl6p7lgza6dsjxq454gxhj9t7a0sd8dgfkx7cmtwd68yetpd5s9xar0wfjn5gpc8qhrsdfq24f5ggrxdaezqsnvda3kkum5wfjkzmfqf3jkgem9wgsyuctwdus9xgrcyqcjcgpzgfskx6eqf9hzqnteypzxz7fzypfhg6trddjhygrcyqezcgpzfysywmm5ypxxjemgw3hxjmn8yptk7untd9hxwg3q2d6xjcmtv4ezq7pqxgsxzmnyyqcjqmt0wfjjq6t5v4khxsp5zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zygsxqyjw5qcqp2rzjq0gxwkzc8w6323m55m4jyxcjwmy7stt9hwkwe2qxmy8zpsgg7jcuwz87fcqqeuqqqyqqqqlgqqqqn3qq9q9qrsgqrvgkpnmps664wgkp43l22qsgdw4ve24aca4nymnxddlnp8vh9v2sdxlu5ywdxefsfvm0fq3sesf08uf6q9a2ke0hc9j6z6wlxg5z5kqpu2v9wz";
+    // testnet, with a description hash, a P2PKH fallback and payment secret
+    const FALLBACK: &str = "lntb20m1pvjluezsp5zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zygshp58yjmdan79s6qqdhdzgynm4zwqd5d7xmw5fk98klysy043l2ahrqspp5qqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqypqfpp3x9et2e20v6pu37c5d9vax37wxq72un989qrsgqdj545axuxtnfemtpwkc45hx9d2ft7x04mt8q7y6t0k2dge9e7h8kpy9p34ytyslj3yu569aalz2xdk8xkd7ltxqld94u8h2esmsmacgpghe9k8";
+
+    #[test]
+    fn test_decode() {
+        let invoice = DecodedInvoice::from_str(STORE).unwrap();
+        assert_eq!(invoice.network(), Some(Network::Bitcoin));
+        assert_eq!(invoice.amount_msats, Some(967_878_534));
+        assert_eq!(invoice.timestamp, 1_572_468_703);
+        assert_eq!(invoice.expires_at(), 1_572_468_703 + 604_800);
+        assert_eq!(
+            invoice.payment_hash.to_string(),
+            "462264ede7e14047e9b249da94fefc47f41f7d02ee9b091815a5506bc8abf75f"
+        );
+        assert!(invoice
+            .description
+            .as_deref()
+            .unwrap()
+            .starts_with("Blockstream Store: 88.85 USD"));
+        assert_eq!(invoice.payee, PublicKey::from_str(PAYEE).unwrap());
+        assert_eq!(invoice.min_final_cltv_expiry_delta, 10);
+        assert_eq!(
+            invoice.route_hints,
+            vec![vec![RouteHintHop {
+                src_node_id: PublicKey::from_str(
+                    "03d06758583bb5154774a6eb221b1276c9e82d65bbaceca806d90e20c108f4b1c7"
+                )
+                .unwrap(),
+                short_channel_id: (589_390 << 40) | (3_312 << 16) | 1,
+                fee_base_msat: 1_000,
+                fee_proportional_millionths: 2_500,
+                cltv_expiry_delta: 40,
+            }]]
+        );
+        assert_eq!(
+            DecodedInvoice::from_str(&STORE.to_uppercase()).unwrap(),
+            invoice
+        );
+
+        let invoice = DecodedInvoice::from_str(FALLBACK).unwrap();
+        assert_eq!(invoice.network(), Some(Network::Testnet));
+        assert_eq!(invoice.payee, PublicKey::from_str(PAYEE).unwrap());
+        assert_eq!(invoice.payment_secret, Some([0x11; 32]));
+        assert!(invoice.description_hash.is_some());
+        assert_eq!(invoice.fallbacks.len(), 1);
+        assert_eq!(invoice.fallbacks[0].version, 17);
+        assert_eq!(invoice.features.bits(), vec![8, 14]);
+        assert!(invoice.features.is_set(14) && !invoice.features.is_set(9));
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        // a character changed, failing the checksum
+        let tampered = FALLBACK.replacen("pp5qqqsyq", "pp5qqqsyp", 1);
+        assert!(DecodedInvoice::from_str(&tampered).is_err());
+        assert!(DecodedInvoice::from_str("lnbc1qqqqqqqqqqqqqq").is_err());
+        assert!(DecodedInvoice::from_str("not an invoice").is_err());
+    }
+}
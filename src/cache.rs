@@ -0,0 +1,334 @@
+//! Caching of first-level LNURL responses.
+//!
+//! Clients built with [`crate::Builder::cache`] store the pay responses returned by
+//! `make_request`, keyed by URL, and honour the `Cache-Control` and `ETag` headers of the
+//! service. Withdraw and channel responses carry a single-use `k1` and are never cached,
+//! nor are requests to urls with a `k1` parameter, such as LNURL-auth challenges.
+
+use crate::LnUrlResponse;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Debug;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+/// A cached LNURL response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedResponse {
+    /// The raw JSON body of the response
+    pub body: String,
+    /// The `ETag` of the response, used to revalidate it once stale
+    pub etag: Option<String>,
+    /// When the response becomes stale, `None` if it must always be revalidated
+    pub expires_at: Option<SystemTime>,
+}
+
+impl CachedResponse {
+    /// Whether the response can be used without revalidating it
+    pub fn is_fresh(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| SystemTime::now() < expires_at)
+    }
+}
+
+/// Storage for cached LNURL responses, keyed by URL.
+pub trait ResponseCache: Debug + Send + Sync {
+    /// The cached response for `url`, fresh or stale
+    fn get(&self, url: &str) -> Option<CachedResponse>;
+
+    /// Store `response` for `url`, replacing any previous one
+    fn insert(&self, url: &str, response: CachedResponse);
+
+    /// Forget the response for `url`
+    fn remove(&self, url: &str);
+}
+
+/// An in-memory [`ResponseCache`], evicting the least recently used response once full.
+#[derive(Debug)]
+pub struct InMemoryCache {
+    capacity: usize,
+    entries: Mutex<LruEntries>,
+}
+
+#[derive(Debug, Default)]
+struct LruEntries {
+    responses: HashMap<String, CachedResponse>,
+    /// Urls from least to most recently used
+    order: VecDeque<String>,
+}
+
+impl LruEntries {
+    fn touch(&mut self, url: &str) {
+        if let Some(pos) = self.order.iter().position(|u| u == url) {
+            self.order.remove(pos);
+        }
+        self.order.push_back(url.to_string());
+    }
+}
+
+impl InMemoryCache {
+    /// Create a cache holding up to `capacity` responses
+    pub fn new(capacity: usize) -> Self {
+        InMemoryCache {
+            capacity,
+            entries: Mutex::new(LruEntries::default()),
+        }
+    }
+}
+
+impl Default for InMemoryCache {
+    fn default() -> Self {
+        Self::new(100)
+    }
+}
+
+impl ResponseCache for InMemoryCache {
+    fn get(&self, url: &str) -> Option<CachedResponse> {
+        let mut entries = self.entries.lock().unwrap();
+        let response = entries.responses.get(url).cloned()?;
+        entries.touch(url);
+        Some(response)
+    }
+
+    fn insert(&self, url: &str, response: CachedResponse) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.responses.insert(url.to_string(), response);
+        entries.touch(url);
+        while entries.responses.len() > self.capacity {
+            match entries.order.pop_front() {
+                Some(oldest) => entries.responses.remove(&oldest),
+                None => break,
+            };
+        }
+    }
+
+    fn remove(&self, url: &str) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.responses.remove(url);
+        if let Some(pos) = entries.order.iter().position(|u| u == url) {
+            entries.order.remove(pos);
+        }
+    }
+}
+
+/// Whether responses for `url` may be looked up in or stored to the cache
+pub(crate) fn is_cacheable_url(url: &str) -> bool {
+    match url::Url::parse(url) {
+        Ok(url) => !url.query_pairs().any(|(key, _)| key == "k1"),
+        Err(_) => false,
+    }
+}
+
+/// Build the cache entry for a response from its `Cache-Control` and `ETag` headers,
+/// or `None` if it must not be cached
+pub(crate) fn cache_entry(
+    body: String,
+    cache_control: Option<&str>,
+    etag: Option<&str>,
+) -> Option<CachedResponse> {
+    let mut max_age = None;
+    for directive in cache_control.unwrap_or_default().split(',') {
+        let directive = directive.trim().to_ascii_lowercase();
+        if directive == "no-store" {
+            return None;
+        } else if directive == "no-cache" {
+            max_age = Some(0);
+        } else if let Some(secs) = directive.strip_prefix("max-age=") {
+            if max_age.is_none() {
+                max_age = secs.trim_matches('"').parse::<u64>().ok();
+            }
+        }
+    }
+
+    // without a freshness lifetime or a validator the response can never be reused
+    let max_age = max_age.filter(|secs| *secs > 0);
+    if max_age.is_none() && etag.is_none() {
+        return None;
+    }
+
+    Some(CachedResponse {
+        body,
+        etag: etag.map(str::to_string),
+        expires_at: max_age.map(|secs| SystemTime::now() + Duration::from_secs(secs)),
+    })
+}
+
+/// Store a fresh `response` for `url` in `cache`, if it may be cached
+pub(crate) fn store(
+    cache: &dyn ResponseCache,
+    url: &str,
+    response: &LnUrlResponse,
+    body: String,
+    cache_control: Option<&str>,
+    etag: Option<&str>,
+) {
+    // only pay and keysend responses, the others carry a single-use k1
+    let entry = match response {
+        LnUrlResponse::LnUrlPayResponse(_) | LnUrlResponse::LnUrlKeysendResponse(_) => {
+            cache_entry(body, cache_control, etag)
+        }
+        _ => None,
+    };
+
+    match entry {
+        Some(entry) => cache.insert(url, entry),
+        None => cache.remove(url),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::MockLnUrlServer;
+    use crate::Builder;
+    use std::sync::Arc;
+
+    fn response(body: &str) -> CachedResponse {
+        CachedResponse {
+            body: body.to_string(),
+            etag: None,
+            expires_at: None,
+        }
+    }
+
+    #[test]
+    fn test_lru_eviction() {
+        let cache = InMemoryCache::new(2);
+        cache.insert("a", response("a"));
+        cache.insert("b", response("b"));
+        assert!(cache.get("a").is_some());
+
+        cache.insert("c", response("c"));
+        assert!(cache.get("b").is_none(), "least recently used is evicted");
+        assert_eq!(cache.get("a").unwrap().body, "a");
+        assert_eq!(cache.get("c").unwrap().body, "c");
+
+        cache.remove("a");
+        assert!(cache.get("a").is_none());
+    }
+
+    #[test]
+    fn test_cache_entry() {
+        let body = || "{}".to_string();
+
+        let entry = cache_entry(body(), Some("public, max-age=60"), None).unwrap();
+        assert!(entry.is_fresh());
+
+        let entry = cache_entry(body(), Some("no-cache"), Some("\"v1\"")).unwrap();
+        assert!(!entry.is_fresh());
+        assert_eq!(entry.etag.as_deref(), Some("\"v1\""));
+
+        assert!(cache_entry(body(), Some("no-store, max-age=60"), Some("\"v1\"")).is_none());
+        assert!(cache_entry(body(), Some("max-age=0"), None).is_none());
+        assert!(cache_entry(body(), None, None).is_none());
+    }
+
+    #[test]
+    fn test_cacheable_url() {
+        assert!(is_cacheable_url(
+            "https://example.com/.well-known/lnurlp/alice"
+        ));
+        assert!(!is_cacheable_url(
+            "https://example.com/auth?tag=login&k1=e2af6254a8df433264fa23f67eb8188635d15ce883e8fc020989d5f82ae6f11e"
+        ));
+    }
+
+    #[cfg(feature = "blocking")]
+    #[test]
+    fn test_blocking_response_cache() {
+        let server = MockLnUrlServer::start().unwrap();
+        server.add_pay_request("/pay", 1_000, 1_000_000, "coffee");
+        server.add_withdraw_request("/withdraw", 10_000);
+        let client = Builder::default()
+            .cache(InMemoryCache::default())
+            .build_blocking()
+            .unwrap();
+
+        // fresh responses are served from the cache
+        server.set_cache_control(Some("max-age=60"));
+        let pay = client.make_request(&server.url("/pay")).unwrap();
+        assert_eq!(client.make_request(&server.url("/pay")).unwrap(), pay);
+        assert_eq!(server.requests().len(), 1);
+
+        // withdraw responses are never cached
+        client.make_request(&server.url("/withdraw")).unwrap();
+        client.make_request(&server.url("/withdraw")).unwrap();
+        assert_eq!(server.requests().len(), 3);
+
+        // stale responses are revalidated with their ETag
+        let client = Builder::default()
+            .cache(InMemoryCache::default())
+            .build_blocking()
+            .unwrap();
+        server.set_cache_control(Some("no-cache"));
+        client.make_request(&server.url("/pay")).unwrap();
+        assert_eq!(client.make_request(&server.url("/pay")).unwrap(), pay);
+        assert_eq!(server.requests().len(), 5);
+
+        // a new body without an ETag doesn't inherit the ETag of the previous one
+        let cache = Arc::new(InMemoryCache::default());
+        let client = Builder {
+            cache: Some(cache.clone()),
+            ..Default::default()
+        }
+        .build_blocking()
+        .unwrap();
+        client.make_request(&server.url("/pay")).unwrap();
+        assert!(cache.get(&server.url("/pay")).unwrap().etag.is_some());
+        server.set_cache_control(Some("max-age=60"));
+        server.set_etag(false);
+        client.make_request(&server.url("/pay")).unwrap();
+        assert_eq!(cache.get(&server.url("/pay")).unwrap().etag, None);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_async_response_cache() {
+        let server = MockLnUrlServer::start().unwrap();
+        server.add_pay_request("/pay", 1_000, 1_000_000, "coffee");
+        server.add_withdraw_request("/withdraw", 10_000);
+        let client = Builder::default()
+            .cache(InMemoryCache::default())
+            .build_async()
+            .unwrap();
+
+        // fresh responses are served from the cache
+        server.set_cache_control(Some("max-age=60"));
+        let pay = client.make_request(&server.url("/pay")).await.unwrap();
+        assert_eq!(client.make_request(&server.url("/pay")).await.unwrap(), pay);
+        assert_eq!(server.requests().len(), 1);
+
+        // withdraw responses are never cached
+        client.make_request(&server.url("/withdraw")).await.unwrap();
+        client.make_request(&server.url("/withdraw")).await.unwrap();
+        assert_eq!(server.requests().len(), 3);
+
+        // stale responses are revalidated with their ETag
+        let client = Builder::default()
+            .cache(InMemoryCache::default())
+            .build_async()
+            .unwrap();
+        server.set_cache_control(Some("no-cache"));
+        client.make_request(&server.url("/pay")).await.unwrap();
+        assert_eq!(client.make_request(&server.url("/pay")).await.unwrap(), pay);
+        assert_eq!(server.requests().len(), 5);
+
+        // a new body without an ETag doesn't inherit the ETag of the previous one
+        let cache = Arc::new(InMemoryCache::default());
+        let client = Builder {
+            cache: Some(cache.clone()),
+            ..Default::default()
+        }
+        .build_async()
+        .unwrap();
+        client.make_request(&server.url("/pay")).await.unwrap();
+        assert!(cache.get(&server.url("/pay")).unwrap().etag.is_some());
+        server.set_cache_control(Some("max-age=60"));
+        server.set_etag(false);
+        client.make_request(&server.url("/pay")).await.unwrap();
+        assert_eq!(cache.get(&server.url("/pay")).unwrap().etag, None);
+    }
+}
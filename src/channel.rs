@@ -1,5 +1,9 @@
-use crate::Tag;
-use serde::{Deserialize, Serialize};
+use crate::api::{
+    take_nulls, validate_callback, validate_k1, validate_node_uri, validate_tag, without_set_nulls,
+};
+use crate::{Error, Tag};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ChannelResponse {
@@ -11,4 +15,118 @@ pub struct ChannelResponse {
     pub k1: String,
     /// tag of the request
     pub tag: Tag,
+
+    /// Unknown fields of the response, see [`PayResponse`](crate::pay::PayResponse)
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl ChannelResponse {
+    /// Check that the callback is a url, `k1` is 32 bytes of hex and `uri` is of the
+    /// form `node_key@host:port`
+    pub fn validate(&self) -> Result<(), Error> {
+        validate_tag(&self.tag, Tag::ChannelRequest)?;
+        validate_callback(&self.callback)?;
+        validate_k1(&self.k1)?;
+        validate_node_uri(&self.uri)
+    }
+}
+
+/// An LUD-07 hosted channel request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(remote = "Self")]
+pub struct HostedChannelResponse {
+    /// Remote node address of form node_key@ip_address:port_number
+    pub uri: String,
+    /// Secret to send to the node when requesting the hosted channel
+    pub k1: String,
+    /// Optional alias of the hosting node
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+    /// tag of the request
+    pub tag: Tag,
+
+    /// Unknown fields of the response, see [`PayResponse`](crate::pay::PayResponse)
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl<'de> Deserialize<'de> for HostedChannelResponse {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut json = serde_json::Map::deserialize(deserializer)?;
+        let nulls = take_nulls(&mut json, &["alias"]);
+        let mut hosted = HostedChannelResponse::deserialize(serde_json::Value::Object(json))
+            .map_err(D::Error::custom)?;
+        hosted.extra.extend(nulls);
+        Ok(hosted)
+    }
+}
+
+impl Serialize for HostedChannelResponse {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let set = [("alias", self.alias.is_some())];
+        match without_set_nulls(&self.extra, &set) {
+            Some(extra) => HostedChannelResponse::serialize(
+                &HostedChannelResponse {
+                    extra,
+                    ..self.clone()
+                },
+                serializer,
+            ),
+            None => HostedChannelResponse::serialize(self, serializer),
+        }
+    }
+}
+
+impl HostedChannelResponse {
+    /// Check that `k1` is 32 bytes of hex and `uri` is of the form `node_key@host:port`
+    pub fn validate(&self) -> Result<(), Error> {
+        validate_tag(&self.tag, Tag::HostedChannelRequest)?;
+        validate_k1(&self.k1)?;
+        validate_node_uri(&self.uri)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixtures::{channel, K1};
+
+    #[test]
+    fn test_validate() {
+        assert!(channel().validate().is_ok());
+
+        for k1 in ["", "k1", "opaque-k1_token"] {
+            let invalid = ChannelResponse {
+                k1: k1.to_string(),
+                ..channel()
+            };
+            assert!(matches!(
+                invalid.validate(),
+                Err(Error::InvalidField { field: "k1", .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_validate_hosted() {
+        let hosted = HostedChannelResponse {
+            uri: channel().uri,
+            k1: K1.to_string(),
+            alias: None,
+            tag: Tag::HostedChannelRequest,
+            extra: Default::default(),
+        };
+        assert!(hosted.validate().is_ok());
+
+        let invalid = HostedChannelResponse {
+            k1: "".to_string(),
+            ..hosted
+        };
+        assert!(matches!(
+            invalid.validate(),
+            Err(Error::InvalidField { field: "k1", .. })
+        ));
+    }
 }
@@ -0,0 +1,49 @@
+//! Responses shared by the unit tests of several modules.
+
+use crate::channel::ChannelResponse;
+use crate::pay::PayResponse;
+use crate::withdraw::WithdrawalResponse;
+use crate::Tag;
+
+/// A valid `k1`, 32 bytes of hex
+pub(crate) const K1: &str = "e2af6254a8df433264fa23f67eb8188635d15ce883e8fc020989d5f82ae6f11e";
+
+/// A pay request for 1 to 1,000,000 sats, committing to `metadata`
+pub(crate) fn pay_response(metadata: &str) -> PayResponse {
+    PayResponse {
+        callback: "https://example.com/callback".to_string(),
+        max_sendable: 1_000_000_000,
+        min_sendable: 1_000,
+        tag: Tag::PayRequest,
+        metadata: metadata.to_string(),
+        comment_allowed: None,
+        allows_nostr: None,
+        nostr_pubkey: None,
+        extra: Default::default(),
+    }
+}
+
+/// A withdraw request for 1 to 10 sats
+pub(crate) fn withdrawal() -> WithdrawalResponse {
+    WithdrawalResponse {
+        default_description: "withdraw".to_string(),
+        callback: "https://example.com/withdraw".to_string(),
+        k1: K1.to_string(),
+        max_withdrawable: 10_000,
+        min_withdrawable: Some(1_000),
+        tag: Tag::WithdrawRequest,
+        extra: Default::default(),
+    }
+}
+
+/// A channel request from a node listening on `127.0.0.1:9735`
+pub(crate) fn channel() -> ChannelResponse {
+    ChannelResponse {
+        uri: "02f1a8c87607f415c8f22c00593002775941dea48869ce23096af27b0cfdcc0b69@127.0.0.1:9735"
+            .to_string(),
+        callback: "https://example.com/channel".to_string(),
+        k1: K1.to_string(),
+        tag: Tag::ChannelRequest,
+        extra: Default::default(),
+    }
+}
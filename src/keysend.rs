@@ -0,0 +1,124 @@
+use crate::api::validate_tag;
+use crate::{Error, Tag};
+use bitcoin::secp256k1::PublicKey;
+use serde::{Deserialize, Serialize};
+
+/// The keysend details of a lightning address, served at `/.well-known/keysend/<username>`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeysendResponse {
+    /// tag of the request
+    pub tag: Tag,
+    /// The node to send the keysend payment to
+    pub pubkey: PublicKey,
+    /// Custom TLV records to include in the payment, e.g. to identify the recipient on a
+    /// shared node
+    #[serde(rename = "customData")]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub custom_data: Vec<KeysendCustomData>,
+
+    /// Unknown fields of the response, see [`PayResponse`](crate::pay::PayResponse)
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+/// A custom TLV record of a [`KeysendResponse`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct KeysendCustomData {
+    /// The TLV type, as a decimal string
+    #[serde(rename = "customKey")]
+    pub custom_key: String,
+    /// The value of the record
+    #[serde(rename = "customValue")]
+    pub custom_value: String,
+}
+
+impl KeysendCustomData {
+    /// The TLV type of the record, or `None` if `custom_key` is not a number
+    pub fn record_type(&self) -> Option<u64> {
+        self.custom_key.parse().ok()
+    }
+}
+
+impl KeysendResponse {
+    /// A keysend response for payments to `pubkey`, without custom records
+    pub fn new(pubkey: PublicKey) -> Self {
+        KeysendResponse {
+            tag: Tag::Keysend,
+            pubkey,
+            custom_data: vec![],
+            extra: Default::default(),
+        }
+    }
+
+    /// Add a custom record of type `record_type` to include in payments
+    pub fn custom_record(mut self, record_type: u64, value: impl Into<String>) -> Self {
+        self.custom_data.push(KeysendCustomData {
+            custom_key: record_type.to_string(),
+            custom_value: value.into(),
+        });
+        self
+    }
+
+    /// Check that the custom record types are numbers
+    pub fn validate(&self) -> Result<(), Error> {
+        validate_tag(&self.tag, Tag::Keysend)?;
+
+        if let Some(data) = self.custom_data.iter().find(|d| d.record_type().is_none()) {
+            return Err(Error::InvalidField {
+                field: "customData",
+                reason: format!("customKey {} is not a number", data.custom_key),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{decode_ln_url_response, LnUrlResponse};
+    use std::str::FromStr;
+
+    // as served by Alby
+    const KEYSEND: &str = r#"{"status":"OK","tag":"keysend","pubkey":"030a58b8653d32b99200a2334cfe913e51dc7d155aa0116c176657a4f1722677a3","customData":[{"customKey":"696969","customValue":"017rsl75kNnSke4mMHYE"}]}"#;
+
+    #[test]
+    fn test_decode_keysend() {
+        let keysend = match decode_ln_url_response(KEYSEND).unwrap() {
+            LnUrlResponse::LnUrlKeysendResponse(keysend) => keysend,
+            other => panic!("expected a keysend response, got {:?}", other),
+        };
+
+        let pubkey = PublicKey::from_str(
+            "030a58b8653d32b99200a2334cfe913e51dc7d155aa0116c176657a4f1722677a3",
+        )
+        .unwrap();
+        assert_eq!(keysend.pubkey, pubkey);
+        assert_eq!(keysend.custom_data[0].record_type(), Some(696969));
+        assert_eq!(
+            serde_json::to_value(&keysend).unwrap(),
+            serde_json::from_str::<serde_json::Value>(KEYSEND).unwrap()
+        );
+
+        let built = KeysendResponse::new(pubkey).custom_record(696969, "017rsl75kNnSke4mMHYE");
+        assert_eq!(built.custom_data, keysend.custom_data);
+        assert!(built.validate().is_ok());
+
+        let invalid = KeysendResponse {
+            custom_data: vec![KeysendCustomData {
+                custom_key: "key".to_string(),
+                custom_value: "value".to_string(),
+            }],
+            ..built
+        };
+        assert!(matches!(
+            invalid.validate(),
+            Err(Error::InvalidField {
+                field: "customData",
+                ..
+            })
+        ));
+    }
+}
@@ -3,10 +3,29 @@
 
 pub mod api;
 mod auth;
+#[cfg(any(test, feature = "backend"))]
+pub mod backend;
+pub mod bip353;
+pub mod bolt11;
+#[cfg(any(feature = "blocking", feature = "async"))]
+pub mod cache;
 pub mod channel;
+#[cfg(test)]
+mod fixtures;
+pub mod keysend;
 pub mod lightning_address;
 pub mod lnurl;
+pub mod observer;
 pub mod pay;
+#[cfg(feature = "qr")]
+pub mod qr;
+#[cfg(any(feature = "blocking", feature = "async"))]
+pub mod retry;
+pub mod server;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+#[cfg(any(feature = "blocking", feature = "async"))]
+pub mod tor;
 pub mod withdraw;
 
 #[cfg(any(feature = "async", feature = "async-https"))]
@@ -17,11 +36,22 @@ pub mod blocking;
 pub use auth::get_derivation_path;
 
 pub use api::*;
+use bitcoin::hashes::sha256;
+use bitcoin::Network;
 #[cfg(feature = "blocking")]
 pub use blocking::BlockingClient;
+#[cfg(any(feature = "blocking", feature = "async"))]
+use cache::ResponseCache;
+use observer::RequestObserver;
 #[cfg(any(feature = "async", feature = "async-https"))]
 pub use r#async::AsyncClient;
+#[cfg(any(feature = "blocking", feature = "async"))]
+use retry::{CircuitBreakerConfig, RetryPolicy};
+use std::collections::HashMap;
+use std::sync::Arc;
 use std::{fmt, io};
+#[cfg(any(feature = "blocking", feature = "async"))]
+use tor::TorConfig;
 
 // All this copy-pasted from rust-esplora-client
 
@@ -40,8 +70,48 @@ pub struct Builder {
     pub proxy: Option<String>,
     /// Socket timeout.
     pub timeout: Option<u64>,
+    /// Map of domains to the base URL requests for them should be sent to instead.
+    ///
+    /// This bypasses DNS for the given domains, which is useful for pointing a lightning
+    /// address domain at a local test server or a private deployment.
+    pub resolve_overrides: HashMap<String, String>,
+    /// Optional policy for retrying requests that fail with transient errors
+    #[cfg(any(feature = "blocking", feature = "async"))]
+    pub retry: Option<RetryPolicy>,
+    /// Optional circuit breaker, failing fast on hosts that keep failing
+    #[cfg(any(feature = "blocking", feature = "async"))]
+    pub circuit_breaker: Option<CircuitBreakerConfig>,
+    /// Optional cache for first-level LNURL responses, see [`cache`]
+    #[cfg(any(feature = "blocking", feature = "async"))]
+    pub cache: Option<Arc<dyn ResponseCache>>,
+    /// Maximum size of a response body in bytes, [`DEFAULT_MAX_BODY_SIZE`] if not set
+    pub max_body_size: Option<usize>,
+    /// Optional Tor routing, see [`tor`]
+    ///
+    /// Requests routed through Tor ignore [`Builder::proxy`]. Tor routing is ignored when
+    /// targeting `wasm32`.
+    #[cfg(any(feature = "blocking", feature = "async"))]
+    pub tor: Option<TorConfig>,
+    /// Headers sent with every request, e.g. the API key of a hosted LNURL provider
+    pub headers: HashMap<String, String>,
+    /// Optional `User-Agent` sent with every request
+    pub user_agent: Option<String>,
+    /// Optional observer of every request, see [`observer`]
+    pub observer: Option<Arc<dyn RequestObserver>>,
+    /// Network that invoices must be for, invoices of any network are accepted if not set
+    pub network: Option<Network>,
+    /// Minimum number of seconds an invoice must remain payable for,
+    /// [`DEFAULT_MIN_INVOICE_EXPIRY`] if not set
+    pub min_invoice_expiry: Option<u64>,
 }
 
+/// Default maximum size of a response body, LNURL responses are small JSON documents
+pub const DEFAULT_MAX_BODY_SIZE: usize = 1024 * 1024;
+
+/// Default minimum number of seconds an invoice must remain payable for, leaving time to
+/// route the payment
+pub const DEFAULT_MIN_INVOICE_EXPIRY: u64 = 60;
+
 impl Builder {
     /// Set the proxy of the builder
     pub fn proxy(mut self, proxy: &str) -> Self {
@@ -55,6 +125,84 @@ impl Builder {
         self
     }
 
+    /// Route all requests for `domain` to `base_url`, e.g. `http://127.0.0.1:8080`
+    ///
+    /// The scheme, host and port of matching requests are replaced by those of `base_url`,
+    /// and the path of `base_url` (if any) is prepended to the request path.
+    pub fn resolve(mut self, domain: &str, base_url: &str) -> Self {
+        self.resolve_overrides
+            .insert(domain.to_lowercase(), base_url.to_string());
+        self
+    }
+
+    /// Set the retry policy of the builder
+    ///
+    /// Async clients don't retry when targeting `wasm32`, as there is no timer to back off with.
+    #[cfg(any(feature = "blocking", feature = "async"))]
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Set the circuit breaker of the builder
+    #[cfg(any(feature = "blocking", feature = "async"))]
+    pub fn circuit_breaker(mut self, config: CircuitBreakerConfig) -> Self {
+        self.circuit_breaker = Some(config);
+        self
+    }
+
+    /// Cache first-level LNURL responses in `cache`, e.g. an [`cache::InMemoryCache`]
+    #[cfg(any(feature = "blocking", feature = "async"))]
+    pub fn cache<C: ResponseCache + 'static>(mut self, cache: C) -> Self {
+        self.cache = Some(Arc::new(cache));
+        self
+    }
+
+    /// Set the maximum size of a response body in bytes, larger responses fail with
+    /// [`Error::ResponseTooLarge`]
+    pub fn max_body_size(mut self, bytes: usize) -> Self {
+        self.max_body_size = Some(bytes);
+        self
+    }
+
+    /// Route `.onion` requests, and optionally all others, through Tor
+    #[cfg(any(feature = "blocking", feature = "async"))]
+    pub fn tor(mut self, config: TorConfig) -> Self {
+        self.tor = Some(config);
+        self
+    }
+
+    /// Send the header `name` with every request
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// Set the user agent of the builder
+    pub fn user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = Some(user_agent.to_string());
+        self
+    }
+
+    /// Notify `observer` of every request, e.g. to log them
+    pub fn observer<O: RequestObserver + 'static>(mut self, observer: O) -> Self {
+        self.observer = Some(Arc::new(observer));
+        self
+    }
+
+    /// Reject invoices that are not for `network`, with [`Error::InvoiceNetworkMismatch`]
+    pub fn network(mut self, network: Network) -> Self {
+        self.network = Some(network);
+        self
+    }
+
+    /// Set the minimum number of seconds an invoice must remain payable for, invoices
+    /// expiring sooner are rejected with [`Error::InvoiceExpiresSoon`]
+    pub fn min_invoice_expiry(mut self, secs: u64) -> Self {
+        self.min_invoice_expiry = Some(secs);
+        self
+    }
+
     /// build a blocking client from builder
     #[cfg(feature = "blocking")]
     pub fn build_blocking(self) -> Result<BlockingClient, Error> {
@@ -68,17 +216,81 @@ impl Builder {
     }
 }
 
+/// Parse the [`Builder::resolve_overrides`] into URLs
+#[cfg(any(feature = "blocking", feature = "async"))]
+pub(crate) fn parse_resolve_overrides(
+    overrides: &HashMap<String, String>,
+) -> Result<HashMap<String, url::Url>, Error> {
+    overrides
+        .iter()
+        .map(|(domain, base_url)| {
+            let url = url::Url::parse(base_url)
+                .map_err(|e| Error::Other(format!("Invalid resolve override for {domain}: {e}")))?;
+            Ok((domain.to_lowercase(), url))
+        })
+        .collect()
+}
+
+/// Rewrite `url` according to the resolve overrides, leaving it untouched if its
+/// domain has no override or it cannot be parsed.
+#[cfg(any(feature = "blocking", feature = "async"))]
+pub(crate) fn apply_resolve_overrides(overrides: &HashMap<String, url::Url>, url: &str) -> String {
+    if overrides.is_empty() {
+        return url.to_string();
+    }
+
+    let parsed = match url::Url::parse(url) {
+        Ok(parsed) => parsed,
+        Err(_) => return url.to_string(),
+    };
+    let base = match parsed.host_str().and_then(|host| overrides.get(host)) {
+        Some(base) => base,
+        None => return url.to_string(),
+    };
+
+    let path = format!("{}{}", base.path().trim_end_matches('/'), parsed.path());
+    let mut rewritten = base.clone();
+    rewritten.set_path(&path);
+    rewritten.set_query(parsed.query());
+    rewritten.set_fragment(parsed.fragment());
+
+    rewritten.to_string()
+}
+
 /// Errors that can happen during a sync with a LNURL service
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
     /// Error decoding lnurl
     InvalidLnUrl,
     /// Error decoding lightning address
     InvalidLightningAddress,
+    /// No user with the given name is hosted by this server
+    UnknownUser(String),
+    /// Error resolving a DNS name
+    Dns(String),
+    /// The BIP-353 payment instructions are invalid
+    InvalidPaymentInstructions(String),
     /// Invalid LnURL pay comment
     InvalidComment,
     /// Invalid amount on request
     InvalidAmount,
+    /// The requested amount is outside the range accepted by the pay request
+    AmountOutOfRange {
+        /// The amount that was requested, in millisatoshis
+        msats: u64,
+        /// The minimum sendable amount, in millisatoshis
+        min_sendable: u64,
+        /// The maximum sendable amount, in millisatoshis
+        max_sendable: u64,
+    },
+    /// The service returned a different kind of LNURL response than expected
+    WrongTag {
+        /// The tag of the expected response
+        expected: Tag,
+        /// The tag of the response that was returned
+        found: Tag,
+    },
     /// The BOLT11 invoice returned by the callback could not be parsed
     InvalidInvoice(String),
     /// The BOLT11 invoice amount does not match the requested amount
@@ -89,6 +301,39 @@ pub enum Error {
         /// or `None` if the invoice did not specify an amount
         invoice_msats: Option<u64>,
     },
+    /// The BOLT11 invoice is for a different network than the client's
+    InvoiceNetworkMismatch {
+        /// The network of the client
+        expected: Network,
+        /// The currency prefix of the invoice, e.g. `tb`
+        prefix: String,
+    },
+    /// The BOLT11 invoice does not commit to the pay request's metadata, or to the zap request
+    InvoiceDescriptionHashMismatch {
+        /// The hash of the metadata or zap request
+        expected: sha256::Hash,
+        /// The description hash of the invoice, or `None` if it has none
+        found: Option<sha256::Hash>,
+    },
+    /// The BOLT11 invoice has expired
+    InvoiceExpired {
+        /// When the invoice expired, in seconds since the UNIX epoch
+        expired_at: u64,
+    },
+    /// The BOLT11 invoice expires before the configured minimum expiry
+    InvoiceExpiresSoon {
+        /// Seconds until the invoice expires
+        expires_in: u64,
+        /// The minimum expiry, in seconds
+        min_expiry: u64,
+    },
+    /// The pay request of a lightning address does not identify that address in its metadata
+    IdentifierMismatch {
+        /// The lightning address that was resolved
+        address: String,
+        /// The `text/identifier` or `text/email` of the pay request, if it has one
+        found: Option<String>,
+    },
     /// Error during ureq HTTP request
     #[cfg(feature = "blocking")]
     Ureq(ureq::Error),
@@ -101,6 +346,32 @@ pub enum Error {
     Io(io::Error),
     /// Error decoding JSON
     Json(serde_json::Error),
+    /// Error parsing a URL
+    Url(url::ParseError),
+    /// Error during LNURL-auth, e.g. deriving the linking key or verifying a signature
+    Auth(String),
+    /// Error encrypting or decrypting an AES success action
+    Crypto(String),
+    /// Requests to this host are blocked by the circuit breaker after repeated failures
+    CircuitOpen(String),
+    /// Error returned by a lightning node backend
+    Backend(String),
+    /// Error rendering a QR code
+    Qr(String),
+    /// A field of the LNURL response has an invalid value
+    InvalidField {
+        /// The name of the field, as in the JSON response
+        field: &'static str,
+        /// Why the value is invalid
+        reason: String,
+    },
+    /// The response body is larger than the configured limit
+    ResponseTooLarge {
+        /// The limit, in bytes
+        limit: usize,
+    },
+    /// The service answered with an LNURL error, with this reason
+    Service(String),
     /// Invalid Response
     InvalidResponse,
     /// Other error
@@ -109,7 +380,101 @@ pub enum Error {
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?}", self)
+        match self {
+            Error::InvalidLnUrl => write!(f, "invalid LNURL"),
+            Error::InvalidLightningAddress => write!(f, "invalid lightning address"),
+            Error::UnknownUser(user) => write!(f, "unknown user: {user}"),
+            Error::Dns(e) => write!(f, "DNS resolution failed: {e}"),
+            Error::InvalidPaymentInstructions(e) => {
+                write!(f, "invalid BIP-353 payment instructions: {e}")
+            }
+            Error::InvalidComment => write!(f, "invalid comment"),
+            Error::InvalidAmount => write!(f, "invalid amount"),
+            Error::AmountOutOfRange {
+                msats,
+                min_sendable,
+                max_sendable,
+            } => write!(
+                f,
+                "amount of {msats} msats is outside the accepted range of {min_sendable} to {max_sendable} msats"
+            ),
+            Error::WrongTag { expected, found } => {
+                write!(f, "expected a {expected} response but got a {found} response")
+            }
+            Error::InvalidInvoice(e) => write!(f, "invalid invoice: {e}"),
+            Error::InvoiceAmountMismatch {
+                requested_msats,
+                invoice_msats: Some(invoice_msats),
+            } => write!(
+                f,
+                "invoice is for {invoice_msats} msats but {requested_msats} msats were requested"
+            ),
+            Error::InvoiceAmountMismatch {
+                requested_msats,
+                invoice_msats: None,
+            } => write!(
+                f,
+                "invoice has no amount but {requested_msats} msats were requested"
+            ),
+            Error::InvoiceNetworkMismatch { expected, prefix } => write!(
+                f,
+                "invoice has currency prefix {prefix} but the client is on {expected}"
+            ),
+            Error::InvoiceDescriptionHashMismatch {
+                expected,
+                found: Some(found),
+            } => write!(
+                f,
+                "invoice description hash {found} does not match the expected {expected}"
+            ),
+            Error::InvoiceDescriptionHashMismatch {
+                expected,
+                found: None,
+            } => write!(
+                f,
+                "invoice has no description hash but {expected} was expected"
+            ),
+            Error::InvoiceExpired { expired_at } => {
+                write!(f, "invoice expired at {expired_at}")
+            }
+            Error::InvoiceExpiresSoon {
+                expires_in,
+                min_expiry,
+            } => write!(
+                f,
+                "invoice expires in {expires_in} seconds, less than the minimum of {min_expiry} seconds"
+            ),
+            Error::IdentifierMismatch {
+                address,
+                found: Some(found),
+            } => write!(f, "pay request is for {found} instead of {address}"),
+            Error::IdentifierMismatch {
+                address,
+                found: None,
+            } => write!(f, "pay request does not identify {address}"),
+            #[cfg(feature = "blocking")]
+            Error::Ureq(_) => write!(f, "HTTP request failed"),
+            #[cfg(any(feature = "async", feature = "async-https"))]
+            Error::Reqwest(_) => write!(f, "HTTP request failed"),
+            Error::HttpResponse(status) => write!(f, "HTTP request failed with status {status}"),
+            Error::Io(_) => write!(f, "I/O error"),
+            Error::Json(_) => write!(f, "invalid JSON"),
+            Error::Url(_) => write!(f, "invalid URL"),
+            Error::Auth(e) => write!(f, "LNURL-auth failed: {e}"),
+            Error::Crypto(e) => write!(f, "cryptographic operation failed: {e}"),
+            Error::CircuitOpen(host) => {
+                write!(f, "requests to {host} are blocked after repeated failures")
+            }
+            Error::Backend(e) => write!(f, "lightning backend error: {e}"),
+            Error::Qr(e) => write!(f, "QR code rendering failed: {e}"),
+            Error::InvalidField { field, reason } => write!(f, "invalid {field}: {reason}"),
+            Error::ResponseTooLarge { limit } => {
+                write!(f, "response body is larger than {limit} bytes")
+            }
+            Error::InvalidResponse => write!(f, "invalid response from the LNURL service"),
+            Error::Service(reason) => write!(f, "service error: {reason}"),
+            Error::Other(e) => write!(f, "{e}"),
+        }
     }
 }
 
@@ -126,39 +491,184 @@ macro_rules! impl_error {
     };
 }
 
-impl std::error::Error for Error {}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            #[cfg(feature = "blocking")]
+            Error::Ureq(e) => Some(e),
+            #[cfg(any(feature = "async", feature = "async-https"))]
+            Error::Reqwest(e) => Some(e),
+            Error::Io(e) => Some(e),
+            Error::Json(e) => Some(e),
+            Error::Url(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
 #[cfg(any(feature = "async", feature = "async-https"))]
 impl_error!(::reqwest::Error, Reqwest, Error);
 impl_error!(io::Error, Io, Error);
 impl_error!(serde_json::Error, Json, Error);
+impl_error!(url::ParseError, Url, Error);
+
+#[cfg(test)]
+mod error_tests {
+    use super::*;
+    use std::error::Error as _;
+
+    #[test]
+    fn test_display() {
+        let err = Error::AmountOutOfRange {
+            msats: 1,
+            min_sendable: 1_000,
+            max_sendable: 2_000,
+        };
+        assert_eq!(
+            err.to_string(),
+            "amount of 1 msats is outside the accepted range of 1000 to 2000 msats"
+        );
+
+        let err = Error::WrongTag {
+            expected: Tag::PayRequest,
+            found: Tag::WithdrawRequest,
+        };
+        assert_eq!(
+            err.to_string(),
+            "expected a payRequest response but got a withdrawRequest response"
+        );
+
+        let err = Error::InvoiceNetworkMismatch {
+            expected: Network::Bitcoin,
+            prefix: "tb".to_string(),
+        };
+        assert_eq!(
+            err.to_string(),
+            "invoice has currency prefix tb but the client is on bitcoin"
+        );
+
+        let err = Error::IdentifierMismatch {
+            address: "alice@example.com".to_string(),
+            found: Some("mallory@example.com".to_string()),
+        };
+        assert_eq!(
+            err.to_string(),
+            "pay request is for mallory@example.com instead of alice@example.com"
+        );
+    }
+
+    #[test]
+    fn test_source() {
+        let err = Error::from(serde_json::from_str::<u64>("nope").unwrap_err());
+        assert_eq!(err.to_string(), "invalid JSON");
+        assert!(err
+            .source()
+            .unwrap()
+            .downcast_ref::<serde_json::Error>()
+            .is_some());
+
+        let err = Error::from(url::Url::parse("not a url").unwrap_err());
+        assert!(err.source().is_some());
+        assert!(Error::InvalidLnUrl.source().is_none());
+    }
+}
+
+#[cfg(any(feature = "blocking", feature = "async"))]
+#[cfg(test)]
+mod resolve_tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_resolve_overrides() {
+        let builder = Builder::default()
+            .resolve("example.com", "http://127.0.0.1:8080")
+            .resolve("prefixed.com", "http://localhost:3000/prefixed/");
+        let overrides = parse_resolve_overrides(&builder.resolve_overrides).unwrap();
+
+        assert_eq!(
+            apply_resolve_overrides(&overrides, "https://example.com/.well-known/lnurlp/ben"),
+            "http://127.0.0.1:8080/.well-known/lnurlp/ben"
+        );
+        assert_eq!(
+            apply_resolve_overrides(&overrides, "https://prefixed.com/callback?amount=1000"),
+            "http://localhost:3000/prefixed/callback?amount=1000"
+        );
+        assert_eq!(
+            apply_resolve_overrides(&overrides, "https://other.com/callback?amount=1000"),
+            "https://other.com/callback?amount=1000"
+        );
+    }
+
+    #[test]
+    fn test_invalid_resolve_override() {
+        let builder = Builder::default().resolve("example.com", "not a url");
+        assert!(parse_resolve_overrides(&builder.resolve_overrides).is_err());
+    }
+}
 
 #[cfg(all(feature = "blocking", any(feature = "async", feature = "async-https")))]
 #[cfg(test)]
 mod tests {
+    use crate::bip353::{PaymentTarget, StaticDnsResolver};
     use crate::lightning_address::LightningAddress;
+    use crate::pay::PayResponse;
+    use crate::testing::{Misbehaviour, MockLnUrlServer};
     use crate::LnUrlResponse::LnUrlPayResponse;
-    use crate::{AsyncClient, BlockingClient, Builder};
+    use crate::{AsyncClient, BlockingClient, Builder, Error};
+    use bitcoin::hashes::Hash;
+    use bitcoin::secp256k1::{Keypair, Message, Secp256k1};
     use lightning_invoice::Bolt11Invoice;
-    use nostr::prelude::ZapRequestData;
-    use nostr::{EventBuilder, JsonUtil, Keys};
+    use serde_json::json;
     use std::str::FromStr;
 
-    #[cfg(all(feature = "blocking", any(feature = "async", feature = "async-https")))]
-    async fn setup_clients() -> (BlockingClient, AsyncClient) {
-        let blocking_client = Builder::default().build_blocking().unwrap();
-        let async_client = Builder::default().build_async().unwrap();
+    async fn setup_clients(builder: Builder) -> (BlockingClient, AsyncClient) {
+        let blocking_client = builder.clone().build_blocking().unwrap();
+        let async_client = builder.build_async().unwrap();
 
         (blocking_client, async_client)
     }
 
-    #[cfg(all(feature = "blocking", any(feature = "async", feature = "async-https")))]
+    /// A signed NIP-57 zap request for `msats`, as JSON
+    fn zap_request(msats: u64) -> String {
+        let secp = Secp256k1::new();
+        let keypair = Keypair::from_seckey_slice(&secp, &[0x42; 32]).unwrap();
+        let (pubkey, _) = keypair.x_only_public_key();
+        let created_at = 1_700_000_000;
+        let tags = json!([["relays"], ["amount", msats.to_string()]]);
+
+        // NIP-01 event id: the hash of the serialized event data
+        let data = json!([0, pubkey.to_string(), created_at, 9734, tags, ""]);
+        let id = bitcoin::hashes::sha256::Hash::hash(data.to_string().as_bytes());
+        let sig =
+            secp.sign_schnorr_no_aux_rand(&Message::from_digest(id.to_byte_array()), &keypair);
+
+        json!({
+            "id": id.to_string(),
+            "pubkey": pubkey.to_string(),
+            "created_at": created_at,
+            "kind": 9734,
+            "tags": tags,
+            "content": "",
+            "sig": sig.to_string(),
+        })
+        .to_string()
+    }
+
+    fn setup_server() -> (MockLnUrlServer, String) {
+        let server = MockLnUrlServer::start().unwrap();
+        server.add_pay_request("/lnurlp/ben", 1_000, 100_000_000_000, "Pay to ben");
+        let url = server.url("/lnurlp/ben");
+
+        (server, url)
+    }
+
     #[tokio::test]
     async fn test_get_invoice() {
-        let url = "https://benthecarman.com/.well-known/lnurlp/ben";
-        let (blocking_client, async_client) = setup_clients().await;
+        let (_server, url) = setup_server();
+        let (blocking_client, async_client) = setup_clients(Builder::default()).await;
 
-        let res = blocking_client.make_request(url).unwrap();
-        let res_async = async_client.make_request(url).await.unwrap();
+        let res = blocking_client.make_request(&url).unwrap();
+        let res_async = async_client.make_request(&url).await.unwrap();
 
         // check res_async
         match res_async {
@@ -181,19 +691,27 @@ mod tests {
 
             assert_eq!(invoice.amount_milli_satoshis(), Some(msats));
             assert_eq!(invoice_async.amount_milli_satoshis(), Some(msats));
+
+            assert!(matches!(
+                blocking_client.get_invoice(&pay, 1, None, None),
+                Err(Error::AmountOutOfRange { msats: 1, .. })
+            ));
+            assert!(matches!(
+                async_client.get_invoice(&pay, 1, None, None).await,
+                Err(Error::AmountOutOfRange { msats: 1, .. })
+            ));
         } else {
             panic!("Wrong response type");
         }
     }
 
-    #[cfg(all(feature = "blocking", any(feature = "async", feature = "async-https")))]
     #[tokio::test]
     async fn test_get_zap_invoice() {
-        let url = "https://benthecarman.com/.well-known/lnurlp/ben";
-        let (blocking_client, async_client) = setup_clients().await;
+        let (_server, url) = setup_server();
+        let (blocking_client, async_client) = setup_clients(Builder::default()).await;
 
-        let res = blocking_client.make_request(url).unwrap();
-        let res_async = async_client.make_request(url).await.unwrap();
+        let res = blocking_client.make_request(&url).unwrap();
+        let res_async = async_client.make_request(&url).await.unwrap();
 
         // check res_async
         match res_async {
@@ -204,24 +722,13 @@ mod tests {
         if let LnUrlPayResponse(pay) = res {
             let msats = 1_000_000;
 
-            let keys = Keys::generate();
-            let event = {
-                let data = ZapRequestData {
-                    public_key: keys.public_key(),
-                    relays: vec![],
-                    amount: Some(msats),
-                    lnurl: None,
-                    event_id: None,
-                    event_coordinate: None,
-                };
-                EventBuilder::new_zap_request(data).to_event(&keys).unwrap()
-            };
+            let event = zap_request(msats);
 
             let invoice = blocking_client
-                .get_invoice(&pay, msats, Some(event.as_json()), None)
+                .get_invoice(&pay, msats, Some(event.clone()), None)
                 .unwrap();
             let invoice_async = async_client
-                .get_invoice(&pay, msats, Some(event.as_json()), None)
+                .get_invoice(&pay, msats, Some(event.clone()), None)
                 .await
                 .unwrap();
 
@@ -235,14 +742,13 @@ mod tests {
         }
     }
 
-    #[cfg(all(feature = "blocking", any(feature = "async", feature = "async-https")))]
     #[tokio::test]
     async fn test_get_invoice_with_comment() {
-        let url = "https://primal.net/.well-known/lnurlp/odell";
-        let (blocking_client, async_client) = setup_clients().await;
+        let (_server, url) = setup_server();
+        let (blocking_client, async_client) = setup_clients(Builder::default()).await;
 
-        let res = blocking_client.make_request(url).unwrap();
-        let res_async = async_client.make_request(url).await.unwrap();
+        let res = blocking_client.make_request(&url).unwrap();
+        let res_async = async_client.make_request(&url).await.unwrap();
 
         // check res_async
         match res_async {
@@ -273,11 +779,14 @@ mod tests {
         }
     }
 
-    #[cfg(all(feature = "blocking", any(feature = "async", feature = "async-https")))]
     #[tokio::test]
     async fn test_get_invoice_ln_addr() {
+        let server = MockLnUrlServer::start().unwrap();
         let ln_addr = LightningAddress::from_str("jack@cash.app").unwrap();
-        let (blocking_client, async_client) = setup_clients().await;
+        server.add_lightning_address(&ln_addr);
+
+        let builder = Builder::default().resolve(ln_addr.domain(), server.base_url());
+        let (blocking_client, async_client) = setup_clients(builder).await;
 
         let res = blocking_client
             .make_request(ln_addr.lnurlp_url().as_str())
@@ -312,4 +821,132 @@ mod tests {
             panic!("Wrong response type");
         }
     }
+
+    #[tokio::test]
+    async fn test_resolve_human_readable_name_fallback() {
+        let server = MockLnUrlServer::start().unwrap();
+        let alice = LightningAddress::from_str("alice@example.com").unwrap();
+        server.add_lightning_address(&alice);
+        server.add_withdraw_request("/.well-known/lnurlp/bob", 10_000);
+        server.add_pay_request("/.well-known/lnurlp/carol", 1_000, 10_000, "Pay to carol");
+
+        let builder = Builder::default().resolve(alice.domain(), server.base_url());
+        let (blocking_client, async_client) = setup_clients(builder).await;
+        let dns = StaticDnsResolver::new();
+
+        for name in [
+            "₿alice@example.com",
+            "₿bob@example.com",
+            "₿carol@example.com",
+        ] {
+            let res = blocking_client.resolve_human_readable_name(&dns, name);
+            let res_async = async_client.resolve_human_readable_name(&dns, name).await;
+            for res in [res, res_async] {
+                match (name, res) {
+                    ("₿alice@example.com", Ok(PaymentTarget::LnUrlPay(_))) => {}
+                    ("₿bob@example.com", Err(Error::WrongTag { .. })) => {}
+                    ("₿carol@example.com", Err(Error::IdentifierMismatch { .. })) => {}
+                    (name, res) => panic!("unexpected result for {}: {:?}", name, res),
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_invoice_wrong_amount() {
+        let (server, url) = setup_server();
+        server.set_misbehaviour(Some(Misbehaviour::WrongAmount));
+        let (blocking_client, async_client) = setup_clients(Builder::default()).await;
+
+        let pay = match async_client.make_request(&url).await.unwrap() {
+            LnUrlPayResponse(pay) => pay,
+            _ => panic!("Wrong response type"),
+        };
+
+        let msats = 1_000_000;
+        assert!(matches!(
+            blocking_client.get_invoice(&pay, msats, None, None),
+            Err(Error::InvoiceAmountMismatch { .. })
+        ));
+        assert!(matches!(
+            async_client.get_invoice(&pay, msats, None, None).await,
+            Err(Error::InvoiceAmountMismatch { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_get_invoice_error_response() {
+        let (server, url) = setup_server();
+        let (blocking_client, async_client) = setup_clients(Builder::default()).await;
+
+        let pay = match async_client.make_request(&url).await.unwrap() {
+            LnUrlPayResponse(pay) => pay,
+            _ => panic!("Wrong response type"),
+        };
+
+        let msats = 1_000_000;
+        server.set_misbehaviour(Some(Misbehaviour::ErrorResponse("nope".to_string())));
+        assert!(matches!(
+            blocking_client.get_invoice(&pay, msats, None, None),
+            Err(Error::Service(ref reason)) if reason == "nope"
+        ));
+        assert!(matches!(
+            async_client.get_invoice(&pay, msats, None, None).await,
+            Err(Error::Service(ref reason)) if reason == "nope"
+        ));
+
+        server.set_misbehaviour(Some(Misbehaviour::MissingInvoice));
+        assert!(matches!(
+            blocking_client.get_invoice(&pay, msats, None, None),
+            Err(Error::InvalidResponse)
+        ));
+        assert!(matches!(
+            async_client.get_invoice(&pay, msats, None, None).await,
+            Err(Error::InvalidResponse)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_untrusted_responses() {
+        let (server, url) = setup_server();
+        let (blocking_client, async_client) = setup_clients(Builder::default()).await;
+        let pay = match async_client.make_request(&url).await.unwrap() {
+            LnUrlPayResponse(pay) => pay,
+            _ => panic!("Wrong response type"),
+        };
+        server.add_pay_response(
+            "/inverted",
+            PayResponse {
+                min_sendable: pay.max_sendable + 1,
+                ..pay
+            },
+        );
+
+        let inverted = server.url("/inverted");
+        assert!(matches!(
+            blocking_client.make_request(&inverted),
+            Err(Error::InvalidField {
+                field: "maxSendable",
+                ..
+            })
+        ));
+        assert!(matches!(
+            async_client.make_request(&inverted).await,
+            Err(Error::InvalidField {
+                field: "maxSendable",
+                ..
+            })
+        ));
+
+        let builder = Builder::default().max_body_size(64);
+        let (blocking_client, async_client) = setup_clients(builder).await;
+        assert!(matches!(
+            blocking_client.make_request(&url),
+            Err(Error::ResponseTooLarge { limit: 64 })
+        ));
+        assert!(matches!(
+            async_client.make_request(&url).await,
+            Err(Error::ResponseTooLarge { limit: 64 })
+        ));
+    }
 }
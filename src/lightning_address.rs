@@ -1,20 +1,36 @@
 use crate::lnurl::LnUrl;
 use crate::Error;
-use email_address::EmailAddress;
 use serde::{Deserialize, Deserializer, Serialize};
 use std::fmt::{Display, Formatter};
 use std::str::FromStr;
+use url::Url;
 
+/// Characters allowed in the local part of an email address besides alphanumerics and `.`
+const EMAIL_LOCAL_PART_SPECIALS: &str = "!#$%&'*+-/=?^_`{|}~";
+/// Characters allowed in the local part of a LUD-16 address besides `a-z0-9`
+const LUD16_LOCAL_PART_SPECIALS: &str = "-_.+";
+
+/// A LUD-16 lightning address of the form `<username>@<domain>`.
+///
+/// Addresses are normalised on construction: the local part is lowercased and the domain is
+/// converted to its lowercase ASCII (punycode) form, so equality and hashing are case-insensitive
+/// and internationalised domains compare equal to their punycode encoding.
 #[derive(Debug, PartialEq, Clone, Eq, Hash)]
 pub struct LightningAddress {
-    value: EmailAddress,
+    local_part: String,
+    domain: String,
 }
 
 impl LightningAddress {
+    /// Parse a lightning address, accepting any local part that is valid for an email address.
     pub fn new(value: &str) -> Result<Self, Error> {
-        EmailAddress::from_str(value)
-            .map(|value| LightningAddress { value })
-            .map_err(|_| Error::InvalidLightningAddress)
+        Self::parse(value, false)
+    }
+
+    /// Parse a lightning address, rejecting local parts with characters other than the
+    /// `a-z0-9-_.+` allowed by LUD-16. Uppercase letters are accepted and lowercased.
+    pub fn new_strict(value: &str) -> Result<Self, Error> {
+        Self::parse(value, true)
     }
 
     pub fn from_domain_and_local_part(domain: &str, local_part: &str) -> Result<Self, Error> {
@@ -22,18 +38,66 @@ impl LightningAddress {
         LightningAddress::new(&string)
     }
 
+    fn parse(value: &str, strict: bool) -> Result<Self, Error> {
+        let (local_part, domain) = value
+            .rsplit_once('@')
+            .ok_or(Error::InvalidLightningAddress)?;
+
+        let local_part = local_part.to_lowercase();
+        if !is_valid_local_part(&local_part, strict) {
+            return Err(Error::InvalidLightningAddress);
+        }
+        let domain = normalize_domain(domain).ok_or(Error::InvalidLightningAddress)?;
+
+        Ok(LightningAddress { local_part, domain })
+    }
+
+    /// The normalised local part (username) of the address
+    pub fn local_part(&self) -> &str {
+        &self.local_part
+    }
+
+    /// The normalised domain of the address, in its ASCII (punycode) form
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+
+    /// Whether the address is hosted on a Tor onion service
+    #[inline]
+    pub fn is_onion(&self) -> bool {
+        self.domain.ends_with(".onion")
+    }
+
+    /// The LUD-16 pay request url, served over `http` for onion domains and `https` otherwise
     #[inline]
     pub fn lnurlp_url(&self) -> String {
-        format!(
-            "https://{}/.well-known/lnurlp/{}",
-            self.value.domain(),
-            self.value.local_part()
-        )
+        self.well_known_url("lnurlp")
+    }
+
+    /// The url of the keysend details of the address, at `/.well-known/keysend/<username>`
+    #[inline]
+    pub fn keysend_url(&self) -> String {
+        self.well_known_url("keysend")
+    }
+
+    /// The url of `/.well-known/<kind>/<username>` on the address' domain.
+    ///
+    /// The local part is percent-encoded as a single path segment, so characters such as
+    /// `/`, `?` and `#` that are valid in an email address can't change the url's path or query.
+    fn well_known_url(&self, kind: &str) -> String {
+        let scheme = if self.is_onion() { "http" } else { "https" };
+        let mut url = Url::parse(&format!("{}://{}/.well-known", scheme, self.domain))
+            .expect("a valid domain gives a valid url");
+        url.path_segments_mut()
+            .expect("http urls have a path")
+            .push(kind)
+            .push(&self.local_part);
+        url.to_string()
     }
 
     #[inline]
     pub fn lnurl(&self) -> LnUrl {
-        LnUrl::from_url(self.lnurlp_url())
+        LnUrl::from_url(self.lnurlp_url()).expect("a valid domain gives a valid url")
     }
 }
 
@@ -50,7 +114,7 @@ impl Serialize for LightningAddress {
     where
         S: serde::Serializer,
     {
-        serializer.serialize_str(self.value.as_str())
+        serializer.collect_str(self)
     }
 }
 
@@ -72,16 +136,55 @@ impl PartialOrd for LightningAddress {
 
 impl Ord for LightningAddress {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.value.as_str().cmp(other.value.as_str())
+        (&self.local_part, &self.domain).cmp(&(&other.local_part, &other.domain))
     }
 }
 
 impl Display for LightningAddress {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.value)
+        write!(f, "{}@{}", self.local_part, self.domain)
     }
 }
 
+/// Check a lowercased local part is a dot-atom of allowed characters
+fn is_valid_local_part(local_part: &str, strict: bool) -> bool {
+    let specials = if strict {
+        LUD16_LOCAL_PART_SPECIALS
+    } else {
+        EMAIL_LOCAL_PART_SPECIALS
+    };
+    let valid_char = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || specials.contains(c);
+
+    !local_part.is_empty()
+        && local_part.len() <= 64
+        && local_part.split('.').all(|atom| !atom.is_empty())
+        && local_part.chars().all(|c| c == '.' || valid_char(c))
+}
+
+/// Convert a domain to its lowercase ASCII form, returning `None` if it is not a valid domain name
+fn normalize_domain(domain: &str) -> Option<String> {
+    let domain = match url::Host::parse(domain).ok()? {
+        url::Host::Domain(domain) => domain,
+        _ => return None,
+    };
+
+    let valid_label = |label: &str| {
+        !label.is_empty()
+            && label.len() <= 63
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+    };
+
+    if domain.len() > 253 || !domain.split('.').all(valid_label) {
+        return None;
+    }
+
+    Some(domain)
+}
+
 #[cfg(test)]
 mod test {
     use crate::lightning_address::LightningAddress;
@@ -92,19 +195,108 @@ mod test {
     fn test_parsing() {
         let address = LightningAddress::from_str("ben@opreturnbot.com");
         assert!(address.is_ok());
+        let address = address.unwrap();
+        assert_eq!(
+            address.lnurlp_url(),
+            "https://opreturnbot.com/.well-known/lnurlp/ben"
+        );
+        assert_eq!(
+            address.keysend_url(),
+            "https://opreturnbot.com/.well-known/keysend/ben"
+        );
+    }
+
+    #[test]
+    fn test_onion_parsing() {
+        let address = LightningAddress::from_str(
+            "ben@2gzyxa5ihm7nsggfxnu52rck2vv4rvmdlkiu3zzui5du4xyclen53wid.onion",
+        )
+        .unwrap();
+        assert!(address.is_onion());
+        assert_eq!(
+            address.lnurlp_url(),
+            "http://2gzyxa5ihm7nsggfxnu52rck2vv4rvmdlkiu3zzui5du4xyclen53wid.onion/.well-known/lnurlp/ben"
+        );
+    }
+
+    #[test]
+    fn test_case_normalisation() {
+        let address = LightningAddress::from_str("Ben@OPReturnBot.com").unwrap();
+        assert_eq!(address.to_string(), "ben@opreturnbot.com");
+        assert_eq!(
+            address,
+            LightningAddress::from_str("ben@opreturnbot.com").unwrap()
+        );
         assert_eq!(
-            address.unwrap().lnurlp_url(),
+            address.lnurlp_url(),
             "https://opreturnbot.com/.well-known/lnurlp/ben"
         );
     }
 
+    #[test]
+    fn test_idn_parsing() {
+        let address = LightningAddress::from_str("satoshi@bücher.example").unwrap();
+        assert_eq!(address.domain(), "xn--bcher-kva.example");
+        assert_eq!(
+            address,
+            LightningAddress::from_str("satoshi@xn--bcher-kva.example").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_strict_parsing() {
+        assert!(LightningAddress::new_strict("alice.b-c_d+tips@example.com").is_ok());
+        assert!(LightningAddress::new_strict("Alice@example.com").is_ok());
+        assert!(LightningAddress::new_strict("alice!@example.com").is_err());
+        assert!(LightningAddress::new_strict("al/ice@example.com").is_err());
+
+        assert!(LightningAddress::new("alice!@example.com").is_ok());
+    }
+
     #[test]
     fn test_invalid_parsing() {
         assert!(LightningAddress::from_str("invalid").is_err());
         assert!(LightningAddress::from_str("####").is_err());
+        assert!(LightningAddress::from_str("@example.com").is_err());
+        assert!(LightningAddress::from_str("alice@").is_err());
+        assert!(LightningAddress::from_str("al..ice@example.com").is_err());
+        assert!(LightningAddress::from_str("alice@-example.com").is_err());
+        assert!(LightningAddress::from_str("alice@127.0.0.1").is_err());
+        assert!(LightningAddress::from_str("al ice@example.com").is_err());
         assert!(LightningAddress::from_str("LNURL1DP68GURN8GHJ7UM9WFMXJCM99E3K7MF0V9CXJ0M385EKVCENXC6R2C35XVUKXEFCV5MKVV34X5EKZD3EV56NYD3HXQURZEPEXEJXXEPNXSCRVWFNV9NXZCN9XQ6XYEFHVGCXXCMYXYMNSERXFQ5FNS").is_err());
     }
 
+    #[test]
+    fn test_url_escaping() {
+        let address = LightningAddress::new("a#b@x.com").unwrap();
+        assert_eq!(
+            address.lnurlp_url(),
+            "https://x.com/.well-known/lnurlp/a%23b"
+        );
+
+        let address = LightningAddress::new("a?x=1@x.com").unwrap();
+        assert_eq!(
+            address.lnurlp_url(),
+            "https://x.com/.well-known/lnurlp/a%3Fx=1"
+        );
+
+        let address = LightningAddress::new("a/b@x.com").unwrap();
+        assert_eq!(
+            address.lnurlp_url(),
+            "https://x.com/.well-known/lnurlp/a%2Fb"
+        );
+
+        let address = LightningAddress::new("100%@x.com").unwrap();
+        assert_eq!(
+            address.lnurlp_url(),
+            "https://x.com/.well-known/lnurlp/100%25"
+        );
+        assert_eq!(
+            address.keysend_url(),
+            "https://x.com/.well-known/keysend/100%25"
+        );
+    }
+
     #[test]
     fn test_lnurl() {
         let address = LightningAddress::from_str("ben@opreturnbot.com").unwrap();
@@ -1,29 +1,149 @@
 use crate::lightning_address::LightningAddress;
 use crate::Error;
+use bech32::primitives::checksum::Checksum;
+use bech32::primitives::decode::CheckedHrpstring;
+use bech32::{Bech32, Hrp};
+use percent_encoding::percent_decode_str;
 use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
 use std::str::FromStr;
+use url::Url;
 
+/// The bech32 checksum without its limit on the length of the code.
+///
+/// LUD-01 LNURLs are bech32 encoded but routinely longer than the 1023 characters
+/// bech32 is designed for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LnUrlChecksum {}
+
+impl Checksum for LnUrlChecksum {
+    type MidstateRepr = <Bech32 as Checksum>::MidstateRepr;
+    const CODE_LENGTH: usize = usize::MAX;
+    const CHECKSUM_LENGTH: usize = Bech32::CHECKSUM_LENGTH;
+    const GENERATOR_SH: [Self::MidstateRepr; 5] = Bech32::GENERATOR_SH;
+    const TARGET_RESIDUE: Self::MidstateRepr = Bech32::TARGET_RESIDUE;
+}
+
+const LNURL_HRP: Hrp = Hrp::parse_unchecked("lnurl");
+
+/// An LNURL, i.e. an http(s) url encoded as bech32.
+///
+/// The url is validated on construction and kept as it was given, so that encoding it
+/// again gives back the same LNURL.
 #[derive(Debug, PartialEq, Clone, Ord, PartialOrd, Eq, Hash)]
 pub struct LnUrl {
-    pub url: String,
+    url: Url,
+    raw: String,
+}
+
+/// An LUD-04 action being authorized
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AuthAction {
+    Register,
+    Login,
+    Link,
+    Auth,
+}
+
+impl FromStr for AuthAction {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "register" => Ok(AuthAction::Register),
+            "login" => Ok(AuthAction::Login),
+            "link" => Ok(AuthAction::Link),
+            "auth" => Ok(AuthAction::Auth),
+            _ => Err(Error::InvalidLnUrl),
+        }
+    }
+}
+
+/// What an LNURL is for, as far as can be told from its url alone
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LnUrlKind {
+    /// An LUD-04 auth challenge
+    Auth {
+        /// The hex encoded challenge to sign
+        k1: String,
+        /// The action being authorized, `None` if missing or unknown
+        action: Option<AuthAction>,
+    },
+    /// An LUD-08 fast withdraw, carrying the withdraw request in its query
+    FastWithdraw,
+    /// The LUD-16 pay request of a lightning address
+    LightningAddressPay,
+    /// Anything else, which has to be fetched to know what it is
+    Unknown,
 }
 
 impl LnUrl {
+    /// Encode as a lowercase bech32 LNURL
     #[inline]
-    pub fn encode(&self) -> String {
-        bech32::encode::<bech32::Bech32>(bech32::Hrp::parse("lnurl").unwrap(), self.url.as_bytes())
-            .unwrap()
+    pub fn encode(&self) -> Result<String, Error> {
+        bech32::encode_lower::<LnUrlChecksum>(LNURL_HRP, self.raw.as_bytes())
+            .map_err(|_| Error::InvalidLnUrl)
+    }
+
+    /// Encode as an uppercase bech32 LNURL, which QR codes can store in the more compact
+    /// alphanumeric mode
+    #[inline]
+    pub fn encode_upper(&self) -> Result<String, Error> {
+        bech32::encode_upper::<LnUrlChecksum>(LNURL_HRP, self.raw.as_bytes())
+            .map_err(|_| Error::InvalidLnUrl)
+    }
+
+    /// The parsed url
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// The url exactly as it was encoded
+    pub fn as_str(&self) -> &str {
+        &self.raw
+    }
+
+    /// What the LNURL is for, from the query pairs and path of its url
+    pub fn kind(&self) -> LnUrlKind {
+        let query: HashMap<_, _> = self.url.query_pairs().collect();
+        match query.get("tag").map(AsRef::as_ref) {
+            Some("login") => {
+                if let Some(k1) = query.get("k1") {
+                    return LnUrlKind::Auth {
+                        k1: k1.to_string(),
+                        action: query.get("action").and_then(|a| a.parse().ok()),
+                    };
+                }
+            }
+            Some("withdrawRequest")
+                if ["k1", "callback", "maxWithdrawable"]
+                    .iter()
+                    .all(|key| query.contains_key(*key)) =>
+            {
+                return LnUrlKind::FastWithdraw
+            }
+            _ => {}
+        }
+
+        if self.lightning_address().is_some() {
+            LnUrlKind::LightningAddressPay
+        } else {
+            LnUrlKind::Unknown
+        }
     }
 
     pub fn is_lnurl_auth(&self) -> bool {
-        self.url.contains("tag=login") && self.url.contains("k1=")
+        matches!(self.kind(), LnUrlKind::Auth { .. })
     }
 
     pub fn lightning_address(&self) -> Option<LightningAddress> {
-        let url = url::Url::from_str(&self.url).ok()?;
-        let local_part = url.path().strip_prefix("/.well-known/lnurlp/")?;
-        LightningAddress::from_domain_and_local_part(url.host_str()?, local_part).ok()
+        let local_part = self.url.path().strip_prefix("/.well-known/lnurlp/")?;
+        if local_part.contains('/') {
+            return None;
+        }
+        let local_part = percent_decode_str(local_part).decode_utf8().ok()?;
+        LightningAddress::from_domain_and_local_part(self.url.host_str()?, &local_part).ok()
     }
 
     #[inline]
@@ -31,15 +151,24 @@ impl LnUrl {
         LnUrl::from_str(&lnurl)
     }
 
+    /// Create an LNURL from an http(s) url
     #[inline]
-    pub fn from_url(url: String) -> LnUrl {
-        LnUrl { url }
+    pub fn from_url(url: String) -> Result<LnUrl, Error> {
+        let parsed = Url::parse(&url).map_err(|_| Error::InvalidLnUrl)?;
+        if !matches!(parsed.scheme(), "https" | "http") || !parsed.has_host() {
+            return Err(Error::InvalidLnUrl);
+        }
+
+        Ok(LnUrl {
+            url: parsed,
+            raw: url,
+        })
     }
 }
 
 impl Display for LnUrl {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.encode())
+        write!(f, "{}", self.encode().map_err(|_| std::fmt::Error)?)
     }
 }
 
@@ -48,7 +177,8 @@ impl Serialize for LnUrl {
     where
         S: serde::Serializer,
     {
-        serializer.serialize_str(&self.encode())
+        let lnurl = self.encode().map_err(serde::ser::Error::custom)?;
+        serializer.serialize_str(&lnurl)
     }
 }
 
@@ -66,13 +196,14 @@ impl FromStr for LnUrl {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Error> {
-        if s.to_lowercase().starts_with("lnurl") {
-            let (_, bytes) = bech32::decode(s).map_err(|_| Error::InvalidLnUrl)?;
-            let url = String::from_utf8(bytes).map_err(|_| Error::InvalidLnUrl)?;
-            Ok(LnUrl { url })
-        } else {
-            Err(Error::InvalidLnUrl)
+        let checked = CheckedHrpstring::new::<LnUrlChecksum>(s).map_err(|_| Error::InvalidLnUrl)?;
+        if checked.hrp() != LNURL_HRP {
+            return Err(Error::InvalidLnUrl);
         }
+
+        let url =
+            String::from_utf8(checked.byte_iter().collect()).map_err(|_| Error::InvalidLnUrl)?;
+        LnUrl::from_url(url)
     }
 }
 
@@ -86,10 +217,40 @@ mod tests {
         let expected =
             "LNURL1DP68GURN8GHJ7UM9WFMXJCM99E3K7MF0V9CXJ0M385EKVCENXC6R2C35XVUKXEFCV5MKVV34X5EKZD3EV56NYD3HXQURZEPEXEJXXEPNXSCRVWFNV9NXZCN9XQ6XYEFHVGCXXCMYXYMNSERXFQ5FNS";
 
-        let lnurl = LnUrl::from_url(url.to_string());
+        let lnurl = LnUrl::from_url(url.to_string()).unwrap();
         assert_eq!(lnurl.to_string().to_uppercase(), expected);
     }
 
+    #[test]
+    fn encode_upper_test() {
+        let url = "https://service.com/api?q=3fc3645b439ce8e7f2553a69e5267081d96dcd340693afabe04be7b0ccd178df";
+        let lnurl = LnUrl::from_url(url.to_string()).unwrap();
+
+        let upper = lnurl.encode_upper().unwrap();
+        assert_eq!(upper, lnurl.encode().unwrap().to_uppercase());
+        assert_eq!(LnUrl::from_str(&upper).unwrap(), lnurl);
+    }
+
+    #[test]
+    fn long_lnurl_test() {
+        let url = format!(
+            "https://service.com/api?{}",
+            (0..100)
+                .map(|i| format!("param{i}=3fc3645b439ce8e7f2553a69e5267081"))
+                .collect::<Vec<_>>()
+                .join("&")
+        );
+        let lnurl = LnUrl::from_url(url.clone()).unwrap();
+
+        let encoded = lnurl.encode().unwrap();
+        assert!(encoded.len() > 1023);
+        assert_eq!(LnUrl::from_str(&encoded).unwrap().as_str(), url);
+        assert_eq!(
+            LnUrl::from_str(&encoded.to_uppercase()).unwrap().as_str(),
+            url
+        );
+    }
+
     #[test]
     fn decode_tests() {
         let str =
@@ -97,22 +258,78 @@ mod tests {
         let expected = "https://service.com/api?q=3fc3645b439ce8e7f2553a69e5267081d96dcd340693afabe04be7b0ccd178df";
 
         let lnurl = LnUrl::decode(str.to_string()).unwrap();
-        assert_eq!(lnurl.url, expected);
+        assert_eq!(lnurl.as_str(), expected);
+
+        // wrong human readable part, bad checksum and mixed case
+        assert!(LnUrl::from_str("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4").is_err());
+        assert!(LnUrl::from_str(&str.replace("FNS", "FNQ")).is_err());
+        assert!(LnUrl::from_str(&str.replace("LNURL1", "lnurl1")).is_err());
     }
 
     #[test]
     fn lnurl_auth_test() {
         let str = "https://service.com/api?q=3fc3645b439ce8e7f2553a69e5267081d96dcd340693afabe04be7b0ccd178df&tag=login&k1=3fc3645b439ce8e7f2553a69e5267081d96dcd340693afabe04be7b0ccd178df";
-        let lnurl = LnUrl::from_url(str.to_string());
+        let lnurl = LnUrl::from_url(str.to_string()).unwrap();
         assert!(lnurl.is_lnurl_auth());
 
         let str = "https://service.com/api?q=3fc3645b439ce8e7f2553a69e5267081d96dcd340693afabe04be7b0ccd178df&tag=login";
-        let lnurl = LnUrl::from_url(str.to_string());
+        let lnurl = LnUrl::from_url(str.to_string()).unwrap();
         assert!(!lnurl.is_lnurl_auth());
 
         let str = "https://service.com/api?q=3fc3645b439ce8e7f2553a69e5267081d96dcd340693afabe04be7b0ccd178df&k1=3fc3645b439ce8e7f2553a69e5267081d96dcd340693afabe04be7b0ccd178df";
-        let lnurl = LnUrl::from_url(str.to_string());
+        let lnurl = LnUrl::from_url(str.to_string()).unwrap();
         assert!(!lnurl.is_lnurl_auth());
+
+        // only the actual query pairs count
+        let str = "https://service.com/api?foo=tag=login&bar=k1=3fc3645b";
+        let lnurl = LnUrl::from_url(str.to_string()).unwrap();
+        assert!(!lnurl.is_lnurl_auth());
+    }
+
+    #[test]
+    fn kind_test() {
+        let k1 = "3fc3645b439ce8e7f2553a69e5267081d96dcd340693afabe04be7b0ccd178df";
+        let lnurl = LnUrl::from_url(format!(
+            "https://service.com/auth?tag=login&k1={k1}&action=link"
+        ))
+        .unwrap();
+        assert_eq!(
+            lnurl.kind(),
+            LnUrlKind::Auth {
+                k1: k1.to_string(),
+                action: Some(AuthAction::Link),
+            }
+        );
+
+        let lnurl = LnUrl::from_url(format!(
+            "https://service.com/withdraw?tag=withdrawRequest&k1={k1}&callback=https%3A%2F%2Fservice.com%2Fcb&maxWithdrawable=1000&defaultDescription=&minWithdrawable=1"
+        ))
+        .unwrap();
+        assert_eq!(lnurl.kind(), LnUrlKind::FastWithdraw);
+
+        let lnurl = LightningAddress::from_str("me@benthecarman.com")
+            .unwrap()
+            .lnurl();
+        assert_eq!(lnurl.kind(), LnUrlKind::LightningAddressPay);
+
+        let lnurl = LnUrl::from_url("https://service.com/withdraw?tag=withdrawRequest".to_string())
+            .unwrap();
+        assert_eq!(lnurl.kind(), LnUrlKind::Unknown);
+    }
+
+    #[test]
+    fn validation_test() {
+        assert!(LnUrl::from_url("not a url".to_string()).is_err());
+        assert!(LnUrl::from_url("ftp://service.com/api".to_string()).is_err());
+
+        // the url is kept as given, so it round trips even if it isn't normalized
+        let lnurl = LnUrl::from_url("https://SERVICE.com?q=1".to_string()).unwrap();
+        assert_eq!(lnurl.url().host_str(), Some("service.com"));
+        let decoded = LnUrl::from_str(&lnurl.encode().unwrap()).unwrap();
+        assert_eq!(decoded.as_str(), "https://SERVICE.com?q=1");
+
+        let json = serde_json::to_string(&lnurl).unwrap();
+        assert_eq!(serde_json::from_str::<LnUrl>(&json).unwrap(), lnurl);
     }
 
     #[test]
@@ -121,5 +338,8 @@ mod tests {
         let lnurl = lightning_address.lnurl();
 
         assert_eq!(lnurl.lightning_address(), Some(lightning_address));
+
+        let escaped = LightningAddress::from_str("a#b@x.com").unwrap();
+        assert_eq!(escaped.lnurl().lightning_address(), Some(escaped));
     }
 }
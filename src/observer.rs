@@ -0,0 +1,107 @@
+//! Observing the requests sent by the clients, e.g. to log them.
+
+use crate::Error;
+use std::fmt::Debug;
+
+/// Called by the clients around every HTTP request they send, including retries.
+///
+/// Set with [`crate::Builder::observer`]. Note that the urls of callbacks carry secrets
+/// such as withdraw `k1`s and LNURL-auth signatures.
+pub trait RequestObserver: Debug + Send + Sync {
+    /// Called before a request to `url` is sent, `attempt` counting retries from 0
+    fn on_request(&self, _url: &str, _attempt: u32) {}
+
+    /// Called once a request to `url` completed, with the HTTP status of the response or
+    /// the error the request failed with
+    fn on_response(&self, _url: &str, _result: Result<u16, &Error>) {}
+}
+
+#[cfg(any(feature = "blocking", feature = "async"))]
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::MockLnUrlServer;
+    use crate::Builder;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Debug, Default)]
+    struct RecordingObserver(Arc<Mutex<Vec<String>>>);
+
+    impl RequestObserver for RecordingObserver {
+        fn on_request(&self, url: &str, attempt: u32) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("request {url} {attempt}"));
+        }
+
+        fn on_response(&self, url: &str, result: Result<u16, &Error>) {
+            let outcome = match result {
+                Ok(status) => status.to_string(),
+                Err(e) => e.to_string(),
+            };
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("response {url} {outcome}"));
+        }
+    }
+
+    /// A builder sending custom headers and recording the requests in `events`
+    fn builder(events: &Arc<Mutex<Vec<String>>>) -> Builder {
+        Builder::default()
+            .header("X-Api-Key", "secret")
+            .user_agent("lnurl-test/1.0")
+            .observer(RecordingObserver(events.clone()))
+    }
+
+    /// Check the headers and observed events of a pay request and its callback
+    fn check_requests(server: &MockLnUrlServer, callback: &str, events: &Mutex<Vec<String>>) {
+        let headers = server.request_headers();
+        assert_eq!(headers.len(), 2);
+        for headers in headers {
+            assert_eq!(headers["x-api-key"], "secret");
+            assert_eq!(headers["user-agent"], "lnurl-test/1.0");
+        }
+
+        let url = server.url("/pay");
+        let callback = format!("{callback}?amount=21000");
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                format!("request {url} 0"),
+                format!("response {url} 200"),
+                format!("request {callback} 0"),
+                format!("response {callback} 200"),
+            ]
+        );
+    }
+
+    #[cfg(feature = "blocking")]
+    #[test]
+    fn test_blocking_headers_and_observer() {
+        let server = MockLnUrlServer::start().unwrap();
+        let pay = server.add_pay_request("/pay", 1_000, 1_000_000, "coffee");
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let client = builder(&events).build_blocking().unwrap();
+
+        client.make_request(&server.url("/pay")).unwrap();
+        client.get_invoice(&pay, 21_000, None, None).unwrap();
+
+        check_requests(&server, &pay.callback, &events);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_async_headers_and_observer() {
+        let server = MockLnUrlServer::start().unwrap();
+        let pay = server.add_pay_request("/pay", 1_000, 1_000_000, "coffee");
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let client = builder(&events).build_async().unwrap();
+
+        client.make_request(&server.url("/pay")).await.unwrap();
+        client.get_invoice(&pay, 21_000, None, None).await.unwrap();
+
+        check_requests(&server, &pay.callback, &events);
+    }
+}
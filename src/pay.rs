@@ -3,21 +3,34 @@ use aes::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
 use aes::Aes256;
 use base64::engine::general_purpose::STANDARD as BASE64_STANDARD;
 use base64::Engine;
-use bech32::primitives::decode::UncheckedHrpstring;
 use bitcoin::hashes::sha256::Hash as Sha256;
 use bitcoin::hashes::Hash;
 use bitcoin::key::XOnlyPublicKey;
+use bitcoin::Network;
 use cbc::{Decryptor, Encryptor};
-use serde::{Deserialize, Serialize};
-use std::convert::{TryFrom, TryInto};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::convert::TryInto;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
 use url::Url;
 
 type Aes256CbcEnc = Encryptor<Aes256>;
 type Aes256CbcDec = Decryptor<Aes256>;
 
+use crate::api::{take_nulls, without_set_nulls};
+use crate::bolt11::{self, DecodedInvoice};
+use crate::lightning_address::LightningAddress;
+use crate::lnurl::LnUrl;
 use crate::{Error, Tag};
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+/// An LUD-06 pay request.
+///
+/// Like the other LNURL responses, it keeps the fields this library doesn't know, e.g. vendor
+/// extensions, in `extra`, so that it serializes back to the JSON the service sent. Explicit
+/// `null`s of its optional fields are kept in `extra` too, until the field is set.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(remote = "Self")]
 pub struct PayResponse {
     /// a second-level url which give you an invoice with a GET request
     /// and an amount
@@ -43,11 +56,48 @@ pub struct PayResponse {
 
     /// Optional, if true, the service allows nostr zaps
     #[serde(rename = "allowsNostr")]
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub allows_nostr: Option<bool>,
 
     /// Optional, if true, the nostr pubkey that will be used to sign zap events
     #[serde(rename = "nostrPubkey")]
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub nostr_pubkey: Option<XOnlyPublicKey>,
+
+    /// Unknown fields of the response, e.g. vendor extensions
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl<'de> Deserialize<'de> for PayResponse {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut json = serde_json::Map::deserialize(deserializer)?;
+        let nulls = take_nulls(&mut json, &["commentAllowed", "allowsNostr", "nostrPubkey"]);
+        let mut pay =
+            PayResponse::deserialize(serde_json::Value::Object(json)).map_err(D::Error::custom)?;
+        pay.extra.extend(nulls);
+        Ok(pay)
+    }
+}
+
+impl Serialize for PayResponse {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let set = [
+            ("commentAllowed", self.comment_allowed.is_some()),
+            ("allowsNostr", self.allows_nostr.is_some()),
+            ("nostrPubkey", self.nostr_pubkey.is_some()),
+        ];
+        match without_set_nulls(&self.extra, &set) {
+            Some(extra) => PayResponse::serialize(
+                &PayResponse {
+                    extra,
+                    ..self.clone()
+                },
+                serializer,
+            ),
+            None => PayResponse::serialize(self, serializer),
+        }
+    }
 }
 
 impl PayResponse {
@@ -58,6 +108,234 @@ impl PayResponse {
     pub fn metadata_hash(&self) -> [u8; 32] {
         Sha256::hash(self.metadata.as_bytes()).to_byte_array()
     }
+
+    /// Decode the metadata into its `[type, content]` entries
+    pub fn decoded_metadata(&self) -> Result<PayMetadata, Error> {
+        let entries: Vec<serde_json::Value> = serde_json::from_str(&self.metadata)?;
+        let entries = entries
+            .into_iter()
+            .map(|entry| match entry.as_array().map(Vec::as_slice) {
+                Some([serde_json::Value::String(t), serde_json::Value::String(content)]) => {
+                    Ok((t.clone(), content.clone()))
+                }
+                _ => Err(Error::InvalidResponse),
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(PayMetadata { entries })
+    }
+
+    /// Check that the callback is a url, the metadata is valid and the limits satisfy
+    /// `1 <= min_sendable <= max_sendable`
+    pub fn validate(&self) -> Result<(), Error> {
+        crate::api::validate_tag(&self.tag, Tag::PayRequest)?;
+        crate::api::validate_callback(&self.callback)?;
+
+        if self.min_sendable < 1 {
+            return Err(Error::InvalidField {
+                field: "minSendable",
+                reason: "must be at least 1".to_string(),
+            });
+        }
+        if self.min_sendable > self.max_sendable {
+            return Err(Error::InvalidField {
+                field: "maxSendable",
+                reason: format!(
+                    "{} is less than minSendable {}",
+                    self.max_sendable, self.min_sendable
+                ),
+            });
+        }
+        if self.decoded_metadata().is_err() {
+            return Err(Error::InvalidField {
+                field: "metadata",
+                reason: "must be a JSON array of [type, content] pairs".to_string(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Check that the metadata identifies `address` in a `text/identifier` or `text/email`
+    /// entry, as LUD-16 requires of lightning address pay requests
+    pub fn verify_identifier(&self, address: &LightningAddress) -> Result<(), Error> {
+        let metadata = self.decoded_metadata()?;
+        let identifiers = metadata
+            .entries
+            .iter()
+            .filter(|(t, _)| t == "text/identifier" || t == "text/email")
+            .map(|(_, content)| content.as_str());
+
+        let mut found = None;
+        for identifier in identifiers {
+            if matches!(LightningAddress::new(identifier), Ok(ref a) if a == address) {
+                return Ok(());
+            }
+            found.get_or_insert(identifier);
+        }
+
+        Err(Error::IdentifierMismatch {
+            address: address.to_string(),
+            found: found.map(str::to_string),
+        })
+    }
+
+    /// Check that `msats` is within the sendable range of this pay request
+    pub fn check_amount(&self, msats: u64) -> Result<(), Error> {
+        if msats < self.min_sendable || msats > self.max_sendable {
+            return Err(Error::AmountOutOfRange {
+                msats,
+                min_sendable: self.min_sendable,
+                max_sendable: self.max_sendable,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// The decoded metadata of a [`PayResponse`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct PayMetadata {
+    /// The `[type, content]` entries, in order
+    pub entries: Vec<(String, String)>,
+}
+
+impl PayMetadata {
+    /// The content of the first entry of type `entry_type`
+    pub fn get(&self, entry_type: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(t, _)| t == entry_type)
+            .map(|(_, content)| content.as_str())
+    }
+
+    /// The short description, from `text/plain`
+    pub fn description(&self) -> Option<&str> {
+        self.get("text/plain")
+    }
+
+    /// The long description, from `text/long-desc`
+    pub fn long_description(&self) -> Option<&str> {
+        self.get("text/long-desc")
+    }
+
+    /// The lightning address being paid, from `text/identifier`
+    pub fn identifier(&self) -> Option<&str> {
+        self.get("text/identifier")
+    }
+
+    /// The email address being paid, from `text/email`
+    pub fn email(&self) -> Option<&str> {
+        self.get("text/email")
+    }
+}
+
+/// Anything that resolves to an LNURL-pay request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PayTarget {
+    LnUrl(LnUrl),
+    LightningAddress(LightningAddress),
+    Url(Url),
+}
+
+impl PayTarget {
+    /// The url of the pay request
+    pub fn url(&self) -> String {
+        match self {
+            PayTarget::LnUrl(lnurl) => lnurl.as_str().to_string(),
+            PayTarget::LightningAddress(address) => address.lnurlp_url(),
+            PayTarget::Url(url) => url.to_string(),
+        }
+    }
+}
+
+impl From<LnUrl> for PayTarget {
+    fn from(lnurl: LnUrl) -> Self {
+        PayTarget::LnUrl(lnurl)
+    }
+}
+
+impl From<LightningAddress> for PayTarget {
+    fn from(address: LightningAddress) -> Self {
+        PayTarget::LightningAddress(address)
+    }
+}
+
+impl From<Url> for PayTarget {
+    fn from(url: Url) -> Self {
+        PayTarget::Url(url)
+    }
+}
+
+impl FromStr for PayTarget {
+    type Err = Error;
+
+    /// Parse a bech32 LNURL, a lightning address or a url, with or without a `lightning:` prefix
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let s = match s.get(..10) {
+            Some(prefix) if prefix.eq_ignore_ascii_case("lightning:") => &s[10..],
+            _ => s,
+        };
+
+        if let Ok(lnurl) = LnUrl::from_str(s) {
+            return Ok(PayTarget::LnUrl(lnurl));
+        }
+        if let Ok(address) = LightningAddress::from_str(s) {
+            return Ok(PayTarget::LightningAddress(address));
+        }
+        match Url::parse(s) {
+            Ok(url) if url.scheme() == "https" || url.scheme() == "http" => Ok(PayTarget::Url(url)),
+            _ => Err(Error::InvalidLnUrl),
+        }
+    }
+}
+
+/// Options for [`crate::AsyncClient::pay_request`] and [`crate::BlockingClient::pay_request`]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PayOptions {
+    /// Comment to send to the service, if it allows comments
+    pub comment: Option<String>,
+    /// Nostr zap request event, as JSON
+    pub zap_request: Option<String>,
+}
+
+/// An invoice fetched for an LNURL-pay request, verified and ready to be paid
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreparedPayment {
+    /// The pay request the invoice was fetched for
+    pub pay_response: PayResponse,
+    /// The invoice returned by the callback, verified to be for `amount_msats`
+    pub invoice: LnURLPayInvoice,
+    /// The amount being paid, in millisatoshis
+    pub amount_msats: u64,
+    /// The decoded metadata of the pay request
+    pub metadata: PayMetadata,
+    /// Action to take once the invoice has been paid
+    pub success_action: Option<SuccessAction>,
+}
+
+impl PreparedPayment {
+    #[cfg(any(feature = "blocking", feature = "async"))]
+    pub(crate) fn new(
+        pay_response: PayResponse,
+        invoice: LnURLPayInvoice,
+        amount_msats: u64,
+        zap_request: Option<&str>,
+    ) -> Result<Self, Error> {
+        invoice.verify_description_hash(zap_request.unwrap_or(&pay_response.metadata))?;
+        let metadata = pay_response.decoded_metadata()?;
+        let success_action = invoice.success_action();
+
+        Ok(PreparedPayment {
+            pay_response,
+            invoice,
+            amount_msats,
+            metadata,
+            success_action,
+        })
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -104,6 +382,12 @@ impl LnURLPayInvoice {
         self.success_action.clone().map(SuccessAction::from_params)
     }
 
+    /// Decode the BOLT11 invoice, verifying its checksum and signature, e.g. to get its
+    /// payment hash and payee
+    pub fn decoded(&self) -> Result<DecodedInvoice, Error> {
+        DecodedInvoice::from_str(&self.pr)
+    }
+
     /// Verify that the BOLT11 invoice's amount equals the requested amount in
     /// millisatoshis, as required by LUD-06 before the invoice is paid.
     ///
@@ -111,7 +395,7 @@ impl LnURLPayInvoice {
     /// invoice, and [`Error::InvoiceAmountMismatch`] if the invoice amount is
     /// absent or differs from `msats`.
     pub fn verify_amount(&self, msats: u64) -> Result<(), Error> {
-        let invoice_msats = parse_bolt11_amount_msats(&self.pr)?;
+        let invoice_msats = bolt11::parse_amount_msats(&self.pr)?;
         if invoice_msats != Some(msats) {
             return Err(Error::InvoiceAmountMismatch {
                 requested_msats: msats,
@@ -121,80 +405,65 @@ impl LnURLPayInvoice {
 
         Ok(())
     }
-}
 
-/// Parse the amount (in millisatoshis) encoded in a BOLT11 invoice without
-/// pulling in a full invoice-parsing dependency.
-///
-/// The amount lives entirely in the human-readable part (HRP) of the invoice,
-/// so we only parse and validate the bech32 structure (via the `bech32` crate)
-/// and read the amount out of the HRP. The HRP is `ln` + a currency prefix
-/// (letters only) + an optional amount, where the amount is one or more digits
-/// followed by an optional multiplier letter (`m`/`u`/`n`/`p`).
-///
-/// Returns `Ok(None)` for an amountless invoice, `Ok(Some(msats))` when an
-/// amount is present, and [`Error::InvalidInvoice`] if the HRP is malformed.
-// `u128::is_multiple_of` is too new for this crate's MSRV.
-#[allow(clippy::manual_is_multiple_of)]
-fn parse_bolt11_amount_msats(invoice: &str) -> Result<Option<u64>, Error> {
-    let invalid = |msg: &str| Error::InvalidInvoice(msg.to_string());
-
-    // Parse the bech32 structure to split off and validate the human-readable
-    // part. We deliberately do not verify the checksum: BOLT11 invoices are not
-    // length-bounded, the amount lives entirely in the HRP, and the invoice
-    // signature is the (possibly malicious) service's own, so a checksum check
-    // would add no protection against a mismatched amount.
-    let parsed =
-        UncheckedHrpstring::new(invoice).map_err(|e| Error::InvalidInvoice(e.to_string()))?;
-    // The HRP is all lower- or all upper-case (mixed case is rejected by the
-    // parser above); normalize so the `ln` prefix and multiplier letters match.
-    let hrp = parsed.hrp().as_str().to_ascii_lowercase();
-
-    if !hrp.starts_with("ln") {
-        return Err(invalid("not a lightning invoice"));
-    }
-
-    // The currency prefix contains no digits, so the amount section (if any)
-    // starts at the first digit in the HRP.
-    let amount = match hrp.find(|c: char| c.is_ascii_digit()) {
-        None => return Ok(None), // amountless invoice
-        Some(idx) => &hrp[idx..],
-    };
-
-    // Split off an optional trailing multiplier letter.
-    let (digits, multiplier) = match amount.chars().last() {
-        Some(c) if c.is_ascii_digit() => (amount, None),
-        Some(c) => (&amount[..amount.len() - 1], Some(c)),
-        None => unreachable!("amount is non-empty"),
-    };
-
-    let value: u128 = digits
-        .parse()
-        .map_err(|_| invalid("invalid amount digits"))?;
-
-    // Convert to millisatoshis. 1 BTC = 100_000_000_000 msat, and the BOLT11
-    // multipliers scale the value by 10^-3 (m), 10^-6 (u), 10^-9 (n), 10^-12 (p)
-    // bitcoin respectively.
-    let msats: u128 = match multiplier {
-        None => value.checked_mul(100_000_000_000),
-        Some('m') => value.checked_mul(100_000_000),
-        Some('u') => value.checked_mul(100_000),
-        Some('n') => value.checked_mul(100),
-        Some('p') => {
-            // A pico-bitcoin amount must be a multiple of 10, as 1 msat is the
-            // smallest representable unit (10 pico-bitcoin).
-            if value % 10 != 0 {
-                return Err(invalid("sub-millisatoshi amount"));
-            }
-            Some(value / 10)
+    /// Verify that the BOLT11 invoice commits to `description` by its description hash, as
+    /// LUD-06 requires. `description` is the pay request's metadata, or the zap request for zaps.
+    ///
+    /// Returns [`Error::InvoiceDescriptionHashMismatch`] if the hash is absent or different.
+    pub fn verify_description_hash(&self, description: &str) -> Result<(), Error> {
+        let expected = Sha256::hash(description.as_bytes());
+        let found = self.decoded()?.description_hash;
+        if found != Some(expected) {
+            return Err(Error::InvoiceDescriptionHashMismatch { expected, found });
         }
-        Some(_) => return Err(invalid("invalid amount multiplier")),
+
+        Ok(())
     }
-    .ok_or_else(|| invalid("amount overflow"))?;
 
-    let msats = u64::try_from(msats).map_err(|_| invalid("amount overflow"))?;
+    /// Verify that the BOLT11 invoice is for `network`, by its currency prefix.
+    ///
+    /// Returns [`Error::InvoiceNetworkMismatch`] if it is for another network.
+    pub fn verify_network(&self, network: Network) -> Result<(), Error> {
+        let prefix = bolt11::parse_currency_prefix(&self.pr)?;
+        if prefix != bolt11::currency_prefix(network) {
+            return Err(Error::InvoiceNetworkMismatch {
+                expected: network,
+                prefix,
+            });
+        }
 
-    Ok(Some(msats))
+        Ok(())
+    }
+
+    /// Verify that the BOLT11 invoice remains payable for at least `min_expiry_secs`.
+    ///
+    /// Returns [`Error::InvoiceExpired`] if it has already expired and
+    /// [`Error::InvoiceExpiresSoon`] if it expires within `min_expiry_secs`.
+    pub fn verify_expiry(&self, min_expiry_secs: u64) -> Result<(), Error> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        self.verify_expiry_at(min_expiry_secs, now)
+    }
+
+    fn verify_expiry_at(&self, min_expiry_secs: u64, now: u64) -> Result<(), Error> {
+        let (timestamp, expiry) = bolt11::parse_expiry(&self.pr)?;
+        let expires_at = timestamp.saturating_add(expiry);
+        if expires_at <= now {
+            return Err(Error::InvoiceExpired {
+                expired_at: expires_at,
+            });
+        }
+        if expires_at - now < min_expiry_secs {
+            return Err(Error::InvoiceExpiresSoon {
+                expires_in: expires_at - now,
+                min_expiry: min_expiry_secs,
+            });
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -254,7 +523,7 @@ pub struct AesParams {
 }
 
 impl AesParams {
-    pub fn new(description: String, text: &str, preimage: &[u8; 32]) -> anyhow::Result<AesParams> {
+    pub fn new(description: String, text: &str, preimage: &[u8; 32]) -> Result<AesParams, Error> {
         let iv = bitcoin::secp256k1::rand::random::<[u8; 16]>();
         let cipher = Aes256CbcEnc::new(preimage.into(), &iv.into());
         let encrypted: Vec<u8> = cipher.encrypt_padded_vec_mut::<Pkcs7>(text.as_bytes());
@@ -268,14 +537,18 @@ impl AesParams {
         })
     }
 
-    pub fn decrypt(&self, preimage: &[u8; 32]) -> anyhow::Result<String> {
+    pub fn decrypt(&self, preimage: &[u8; 32]) -> Result<String, Error> {
         // decode base64
-        let iv = BASE64_STANDARD.decode(&self.iv)?;
-        let ciphertext = BASE64_STANDARD.decode(&self.ciphertext)?;
+        let iv = BASE64_STANDARD
+            .decode(&self.iv)
+            .map_err(|e| Error::Crypto(format!("invalid iv: {e}")))?;
+        let ciphertext = BASE64_STANDARD
+            .decode(&self.ciphertext)
+            .map_err(|e| Error::Crypto(format!("invalid ciphertext: {e}")))?;
 
         // check iv length
         if iv.len() != 16 {
-            return Err(anyhow::anyhow!("iv length is not 16"));
+            return Err(Error::Crypto("iv length is not 16".to_string()));
         }
         // turn into generic array
         let iv: [u8; 16] = iv.try_into().unwrap();
@@ -284,9 +557,10 @@ impl AesParams {
         let cipher = Aes256CbcDec::new(preimage.into(), &iv.into());
         let decrypted: Vec<u8> = cipher
             .decrypt_padded_vec_mut::<Pkcs7>(&ciphertext)
-            .map_err(|_| anyhow::anyhow!("decryption failed"))?;
+            .map_err(|_| Error::Crypto("decryption failed".to_string()))?;
 
-        Ok(String::from_utf8(decrypted)?)
+        String::from_utf8(decrypted)
+            .map_err(|_| Error::Crypto("decrypted text is not valid UTF-8".to_string()))
     }
 }
 
@@ -340,6 +614,7 @@ impl SuccessAction {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::fixtures::pay_response;
     use crate::Response;
 
     #[test]
@@ -354,6 +629,88 @@ mod test {
         assert_eq!(decrypted, text);
     }
 
+    #[test]
+    fn test_decoded_metadata() {
+        let pay = PayResponse {
+            max_sendable: 100_000,
+            ..pay_response(r#"[["text/plain","Pay ben"],["text/identifier","ben@example.com"]]"#)
+        };
+
+        let metadata = pay.decoded_metadata().unwrap();
+        assert_eq!(metadata.description(), Some("Pay ben"));
+        assert_eq!(metadata.identifier(), Some("ben@example.com"));
+        assert_eq!(metadata.email(), None);
+
+        assert!(pay.check_amount(1_000).is_ok());
+        assert!(pay.check_amount(100_000).is_ok());
+        assert!(matches!(
+            pay.check_amount(999),
+            Err(Error::AmountOutOfRange { msats: 999, .. })
+        ));
+        assert!(matches!(
+            pay.check_amount(100_001),
+            Err(Error::AmountOutOfRange { msats: 100_001, .. })
+        ));
+
+        let invalid = PayResponse {
+            metadata: r#"[["text/plain"]]"#.to_string(),
+            ..pay
+        };
+        assert!(invalid.decoded_metadata().is_err());
+    }
+
+    #[test]
+    fn test_verify_identifier() {
+        let ben = LightningAddress::new("ben@example.com").unwrap();
+
+        let identified =
+            pay_response(r#"[["text/plain","Pay ben"],["text/identifier","Ben@EXAMPLE.com"]]"#);
+        assert!(identified.verify_identifier(&ben).is_ok());
+        let emailed =
+            pay_response(r#"[["text/plain","Pay ben"],["text/email","ben@example.com"]]"#);
+        assert!(emailed.verify_identifier(&ben).is_ok());
+
+        let other =
+            pay_response(r#"[["text/plain","Pay alice"],["text/identifier","alice@example.com"]]"#);
+        assert!(matches!(
+            other.verify_identifier(&ben),
+            Err(Error::IdentifierMismatch { found: Some(ref found), .. }) if found == "alice@example.com"
+        ));
+        let anonymous = pay_response(r#"[["text/plain","Pay ben"]]"#);
+        assert!(matches!(
+            anonymous.verify_identifier(&ben),
+            Err(Error::IdentifierMismatch { found: None, .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_pay_target() {
+        let lnurl = "LNURL1DP68GURN8GHJ7UM9WFMXJCM99E3K7MF0V9CXJ0M385EKVCENXC6R2C35XVUKXEFCV5MKVV34X5EKZD3EV56NYD3HXQURZEPEXEJXXEPNXSCRVWFNV9NXZCN9XQ6XYEFHVGCXXCMYXYMNSERXFQ5FNS";
+        assert!(matches!(
+            PayTarget::from_str(lnurl).unwrap(),
+            PayTarget::LnUrl(_)
+        ));
+        assert!(matches!(
+            PayTarget::from_str(&format!("lightning:{lnurl}")).unwrap(),
+            PayTarget::LnUrl(_)
+        ));
+
+        let target = PayTarget::from_str("ben@benthecarman.com").unwrap();
+        assert_eq!(
+            target.url(),
+            "https://benthecarman.com/.well-known/lnurlp/ben"
+        );
+
+        let target = PayTarget::from_str("https://example.com/lnurlp/ben").unwrap();
+        assert_eq!(
+            target,
+            PayTarget::Url(Url::parse("https://example.com/lnurlp/ben").unwrap())
+        );
+
+        assert!(PayTarget::from_str("not a target").is_err());
+        assert!(PayTarget::from_str("ftp://example.com").is_err());
+    }
+
     #[test]
     fn test_parse_verify_settled() {
         let settled = r#"{
@@ -392,7 +749,7 @@ mod test {
 
     #[test]
     fn test_parse_bolt11_amount() {
-        let parse = |s: &str| parse_bolt11_amount_msats(s).unwrap();
+        let parse = |s: &str| bolt11::parse_amount_msats(s).unwrap();
 
         assert_eq!(parse(AMOUNTLESS), None);
         assert_eq!(parse(INV_250_000_000_MSAT), Some(250_000_000));
@@ -406,22 +763,80 @@ mod test {
     fn test_parse_bolt11_amount_invalid() {
         // sub-millisatoshi precision (pico amount not a multiple of 10)
         assert!(matches!(
-            parse_bolt11_amount_msats(SUB_MSAT),
+            bolt11::parse_amount_msats(SUB_MSAT),
             Err(Error::InvalidInvoice(_))
         ));
         // not a lightning invoice
         assert!(matches!(
-            parse_bolt11_amount_msats("not a real invoice"),
+            bolt11::parse_amount_msats("not a real invoice"),
             Err(Error::InvalidInvoice(_))
         ));
         // amount that overflows u64 millisatoshis. No real invoice can carry
         // such a value, so this exercises the HRP parser directly.
         assert!(matches!(
-            parse_bolt11_amount_msats("lnbc99999999999991q"),
+            bolt11::parse_amount_msats("lnbc99999999999991q"),
             Err(Error::InvalidInvoice(_))
         ));
     }
 
+    #[test]
+    fn test_verify_network() {
+        let mainnet = LnURLPayInvoice::new(INV_250_000_000_MSAT.to_string());
+        assert!(mainnet.verify_network(Network::Bitcoin).is_ok());
+        assert!(LnURLPayInvoice::new(AMOUNTLESS.to_uppercase())
+            .verify_network(Network::Bitcoin)
+            .is_ok());
+
+        let testnet = LnURLPayInvoice::new(TESTNET_INV_2_000_000_000_MSAT.to_string());
+        assert!(testnet.verify_network(Network::Testnet).is_ok());
+        match testnet.verify_network(Network::Bitcoin) {
+            Err(Error::InvoiceNetworkMismatch { expected, prefix }) => {
+                assert_eq!(expected, Network::Bitcoin);
+                assert_eq!(prefix, "tb");
+            }
+            other => panic!("expected a network mismatch, got {:?}", other),
+        }
+        assert!(matches!(
+            mainnet.verify_network(Network::Regtest),
+            Err(Error::InvoiceNetworkMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_verify_expiry() {
+        // created at 1496314658 with an expiry of 60 seconds
+        assert_eq!(
+            bolt11::parse_expiry(INV_250_000_000_MSAT).unwrap(),
+            (1_496_314_658, 60)
+        );
+        // no expiry field, defaults to an hour
+        assert_eq!(
+            bolt11::parse_expiry(&INV_2_000_000_000_MSAT.to_uppercase()).unwrap(),
+            (1_496_314_658, 3600)
+        );
+        assert!(bolt11::parse_expiry(AMOUNTLESS).is_err());
+
+        let invoice = LnURLPayInvoice::new(INV_250_000_000_MSAT.to_string());
+        assert!(invoice.verify_expiry_at(30, 1_496_314_658).is_ok());
+        assert!(matches!(
+            invoice.verify_expiry_at(30, 1_496_314_700),
+            Err(Error::InvoiceExpiresSoon {
+                expires_in: 18,
+                min_expiry: 30
+            })
+        ));
+        assert!(matches!(
+            invoice.verify_expiry_at(30, 1_496_314_718),
+            Err(Error::InvoiceExpired {
+                expired_at: 1_496_314_718
+            })
+        ));
+        assert!(matches!(
+            invoice.verify_expiry(0),
+            Err(Error::InvoiceExpired { .. })
+        ));
+    }
+
     #[test]
     fn test_verify_amount_matches() {
         let inv = LnURLPayInvoice::new(INV_250_000_000_MSAT.to_string());
@@ -0,0 +1,211 @@
+//! Rendering LNURLs, lightning addresses and `lightning:` URIs as QR codes.
+//!
+//! LNURLs are encoded in uppercase, as recommended by LUD-01, so that the QR code can
+//! use the denser alphanumeric mode. The error correction level is chosen automatically,
+//! see [`QrMatrix::new`].
+
+use crate::lightning_address::LightningAddress;
+use crate::lnurl::LnUrl;
+use crate::Error;
+use qrcode::{EcLevel, QrCode};
+use std::fmt::Write;
+
+/// Number of light modules around the code, as required by the QR code specification
+const QUIET_ZONE: usize = 4;
+
+/// A rendered QR code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QrMatrix {
+    width: usize,
+    /// Whether each module is dark, row by row
+    modules: Vec<bool>,
+}
+
+impl QrMatrix {
+    /// Encode `data` in the smallest QR code that fits it, using the highest error
+    /// correction level that fits in a code of that size.
+    ///
+    /// The data is split into numeric, alphanumeric and byte segments to keep the code
+    /// as small as possible.
+    pub fn new(data: &str) -> Result<Self, Error> {
+        let qr_err = |e: qrcode::types::QrError| Error::Qr(e.to_string());
+
+        let smallest = QrCode::with_error_correction_level(data, EcLevel::L).map_err(qr_err)?;
+        let code = [EcLevel::H, EcLevel::Q, EcLevel::M]
+            .iter()
+            .find_map(|level| QrCode::with_version(data, smallest.version(), *level).ok())
+            .unwrap_or(smallest);
+
+        let modules = code
+            .to_colors()
+            .into_iter()
+            .map(|color| color == qrcode::Color::Dark)
+            .collect();
+
+        Ok(QrMatrix {
+            width: code.width(),
+            modules,
+        })
+    }
+
+    /// Encode a `lightning:` URI, uppercasing it when its payload is bech32, e.g. an
+    /// LNURL or a BOLT11 invoice
+    pub fn lightning_uri(uri: &str) -> Result<Self, Error> {
+        let payload = match uri.get(..10) {
+            Some(scheme) if scheme.eq_ignore_ascii_case("lightning:") => &uri[10..],
+            _ => return Err(Error::Qr(format!("not a lightning: URI: {uri}"))),
+        };
+
+        if payload.contains('@') {
+            Self::new(&format!("lightning:{payload}"))
+        } else {
+            Self::new(&format!("LIGHTNING:{}", payload.to_uppercase()))
+        }
+    }
+
+    /// Number of modules on each side of the code, without the quiet zone
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Whether the module at column `x` and row `y` is dark
+    pub fn is_dark(&self, x: usize, y: usize) -> bool {
+        self.modules[y * self.width + x]
+    }
+
+    /// Whether the module at `x` and `y`, counted from the corner of the quiet zone,
+    /// is dark
+    fn is_dark_with_quiet_zone(&self, x: usize, y: usize) -> bool {
+        let (x, y) = (x.wrapping_sub(QUIET_ZONE), y.wrapping_sub(QUIET_ZONE));
+        x < self.width && y < self.width && self.is_dark(x, y)
+    }
+
+    /// Render as an SVG image with modules of `module_size` pixels
+    pub fn to_svg(&self, module_size: u32) -> String {
+        let size = self.width + 2 * QUIET_ZONE;
+        let pixels = size as u32 * module_size;
+
+        let mut path = String::new();
+        for y in 0..self.width {
+            for x in 0..self.width {
+                if self.is_dark(x, y) {
+                    let _ = write!(path, "M{},{}h1v1h-1z", x + QUIET_ZONE, y + QUIET_ZONE);
+                }
+            }
+        }
+
+        format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" version=\"1.1\" width=\"{pixels}\" height=\"{pixels}\" viewBox=\"0 0 {size} {size}\" shape-rendering=\"crispEdges\"><rect width=\"{size}\" height=\"{size}\" fill=\"#fff\"/><path d=\"{path}\" fill=\"#000\"/></svg>"
+        )
+    }
+
+    /// Render as a grayscale PNG image with modules of `module_size` pixels
+    pub fn to_png(&self, module_size: u32) -> Result<Vec<u8>, Error> {
+        let module_size = module_size.max(1) as usize;
+        let pixels = (self.width + 2 * QUIET_ZONE) * module_size;
+
+        let mut image = Vec::with_capacity(pixels * pixels);
+        for y in 0..pixels {
+            for x in 0..pixels {
+                let dark = self.is_dark_with_quiet_zone(x / module_size, y / module_size);
+                image.push(if dark { 0 } else { 255 });
+            }
+        }
+
+        let png_err = |e: png::EncodingError| Error::Qr(e.to_string());
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, pixels as u32, pixels as u32);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(png_err)?;
+        writer.write_image_data(&image).map_err(png_err)?;
+        writer.finish().map_err(png_err)?;
+
+        Ok(png)
+    }
+
+    /// Render for a terminal with Unicode half blocks, two rows of modules per line.
+    ///
+    /// Terminals with a dark background draw blocks in a light color, so set
+    /// `dark_background` to draw the light modules as blocks there.
+    pub fn to_unicode(&self, dark_background: bool) -> String {
+        let size = self.width + 2 * QUIET_ZONE;
+        let filled = |x, y| self.is_dark_with_quiet_zone(x, y) != dark_background;
+
+        let mut out = String::new();
+        for y in (0..size).step_by(2) {
+            for x in 0..size {
+                out.push(match (filled(x, y), y + 1 < size && filled(x, y + 1)) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                });
+            }
+            out.push('\n');
+        }
+
+        out
+    }
+}
+
+impl LnUrl {
+    /// Render the uppercase bech32 LNURL as a QR code
+    pub fn qr_code(&self) -> Result<QrMatrix, Error> {
+        QrMatrix::new(&self.encode_upper()?)
+    }
+}
+
+impl LightningAddress {
+    /// Render the LNURL of the address as a QR code, which any LNURL wallet can scan
+    pub fn qr_code(&self) -> Result<QrMatrix, Error> {
+        self.lnurl().qr_code()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_lnurl_qr_code() {
+        let lnurl = LnUrl::from_url("https://service.com/api?q=3fc3645b439ce8e7f2553a69e5267081d96dcd340693afabe04be7b0ccd178df".to_string()).unwrap();
+        let qr = lnurl.qr_code().unwrap();
+
+        // byte mode would need a version 8 code, 49 modules wide
+        assert!(qr.width() < 49);
+        // finder pattern in the top left corner
+        assert!(qr.is_dark(0, 0) && qr.is_dark(6, 6) && !qr.is_dark(1, 1));
+
+        let svg = qr.to_svg(4);
+        assert!(svg.starts_with("<svg") && svg.ends_with("</svg>"));
+
+        let png = qr.to_png(4).unwrap();
+        assert_eq!(&png[1..4], b"PNG");
+
+        let unicode = qr.to_unicode(false);
+        assert_eq!(
+            unicode.lines().count(),
+            (qr.width() + 2 * QUIET_ZONE).div_ceil(2)
+        );
+        assert_ne!(unicode, qr.to_unicode(true));
+    }
+
+    #[test]
+    fn test_lightning_uri_qr_code() {
+        let address = LightningAddress::from_str("ben@zaps.benthecarman.com").unwrap();
+        assert_eq!(
+            address.qr_code().unwrap(),
+            address.lnurl().qr_code().unwrap()
+        );
+
+        let upper = QrMatrix::lightning_uri("lightning:lnurl1dp68gurn8ghj7").unwrap();
+        assert_eq!(
+            upper,
+            QrMatrix::new("LIGHTNING:LNURL1DP68GURN8GHJ7").unwrap()
+        );
+        assert!(QrMatrix::lightning_uri("lightning:ben@zaps.benthecarman.com").is_ok());
+        assert!(QrMatrix::lightning_uri("bitcoin:bc1q").is_err());
+    }
+}
@@ -0,0 +1,437 @@
+//! Retrying requests that fail with transient errors, and backing off from failing hosts.
+
+use crate::Error;
+use bitcoin::secp256k1::rand::random;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How to retry requests that failed with a transient error, i.e. a connection error,
+/// a timeout, or a `429` or `5xx` response.
+///
+/// A `Retry-After` header of a `429` or `503` response is honoured as the least delay
+/// before the next attempt. If it asks to wait longer than [`RetryPolicy::max_backoff`],
+/// the request fails instead.
+///
+/// Only idempotent requests are retried: fetching LNURL responses and polling LUD-21
+/// verify urls. Withdraw, channel and auth callbacks are never retried, and pay callbacks
+/// only when [`RetryPolicy::retry_invoice_requests`] is set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Maximum number of retries after the first attempt
+    pub max_retries: u32,
+    /// Delay before the first retry
+    pub initial_backoff: Duration,
+    /// Upper bound of the delay between retries
+    pub max_backoff: Duration,
+    /// Factor the delay grows by after every retry
+    pub multiplier: u32,
+    /// Randomize each delay between half and all of its value, so that clients
+    /// don't retry in lockstep
+    pub jitter: bool,
+    /// Also retry LNURL-pay callbacks.
+    ///
+    /// A service that created an invoice but failed to return it will create a second one
+    /// when the callback is retried, so this is off by default.
+    pub retry_invoice_requests: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(250),
+            max_backoff: Duration::from_secs(5),
+            multiplier: 2,
+            jitter: true,
+            retry_invoice_requests: false,
+        }
+    }
+}
+
+/// The kind of request being made, deciding whether it may be retried
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RequestKind {
+    /// Fetching an LNURL response or polling a verify url
+    Idempotent,
+    /// An LNURL-pay callback, which creates an invoice
+    Invoice,
+    /// A callback with side effects that must only be sent once
+    Once,
+}
+
+impl RetryPolicy {
+    /// The delay before retry number `retry`, counting from 0
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = self.multiplier.saturating_pow(retry);
+        let delay = self
+            .initial_backoff
+            .checked_mul(factor)
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff);
+
+        if self.jitter {
+            // between 50% and 100% of the delay
+            delay / 2 + delay.mul_f64(random::<f64>() / 2.0)
+        } else {
+            delay
+        }
+    }
+
+    /// The delay before retry number `retry`, at least the `retry_after` delay asked for by
+    /// the service, or `None` if the service asks to wait longer than `max_backoff`
+    pub(crate) fn delay(&self, retry: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        match retry_after {
+            Some(retry_after) if retry_after > self.max_backoff => None,
+            Some(retry_after) => Some(self.backoff(retry).max(retry_after)),
+            None => Some(self.backoff(retry)),
+        }
+    }
+
+    /// Whether a request of `kind` that failed with `error` should be retried, after
+    /// `retries` retries so far
+    pub(crate) fn should_retry(&self, kind: RequestKind, retries: u32, error: &Error) -> bool {
+        let retryable = match kind {
+            RequestKind::Idempotent => true,
+            RequestKind::Invoice => self.retry_invoice_requests,
+            RequestKind::Once => false,
+        };
+
+        retryable && retries < self.max_retries && is_transient(error)
+    }
+}
+
+/// Whether an HTTP status code indicates a transient failure
+pub(crate) fn is_transient_status(status: u16) -> bool {
+    status == 429 || (500..600).contains(&status)
+}
+
+/// The delay asked for by the `Retry-After` header of a response with `status`, if it is
+/// a `429` or `503` and the header is a number of seconds
+pub(crate) fn retry_after(status: u16, header: Option<&str>) -> Option<Duration> {
+    if status != 429 && status != 503 {
+        return None;
+    }
+    let secs = header?.trim().parse().ok()?;
+    Some(Duration::from_secs(secs))
+}
+
+/// Whether `error` is worth retrying
+pub(crate) fn is_transient(error: &Error) -> bool {
+    match error {
+        Error::HttpResponse(status) => is_transient_status(*status),
+        Error::Io(_) => true,
+        #[cfg(feature = "blocking")]
+        Error::Ureq(ureq::Error::Transport(_)) => true,
+        #[cfg(any(feature = "async", feature = "async-https"))]
+        Error::Reqwest(e) => {
+            e.is_timeout()
+                || e.is_connect()
+                || e.status().is_some_and(|s| is_transient_status(s.as_u16()))
+        }
+        _ => false,
+    }
+}
+
+/// When to stop sending requests to a failing host
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CircuitBreakerConfig {
+    /// Number of consecutive failed requests after which the host's circuit opens
+    pub failure_threshold: u32,
+    /// How long requests to the host fail with [`Error::CircuitOpen`] once its circuit
+    /// is open, before a single request is let through to probe it again
+    pub cooldown: Duration,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        CircuitBreakerConfig {
+            failure_threshold: 5,
+            cooldown: Duration::from_secs(30),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct HostState {
+    failures: u32,
+    opened_at: Option<Instant>,
+}
+
+/// Tracks failures per host, shared by all clones of a client
+#[derive(Debug, Clone)]
+pub(crate) struct CircuitBreaker {
+    config: CircuitBreakerConfig,
+    hosts: Arc<Mutex<HashMap<String, HostState>>>,
+}
+
+impl CircuitBreaker {
+    pub(crate) fn new(config: CircuitBreakerConfig) -> Self {
+        CircuitBreaker {
+            config,
+            hosts: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Fail with [`Error::CircuitOpen`] if requests to `host` are currently blocked
+    pub(crate) fn check(&self, host: &str) -> Result<(), Error> {
+        let mut hosts = self.hosts.lock().unwrap();
+        let state = match hosts.get_mut(host) {
+            Some(state) => state,
+            None => return Ok(()),
+        };
+
+        match state.opened_at {
+            Some(opened_at) if opened_at.elapsed() < self.config.cooldown => {
+                Err(Error::CircuitOpen(host.to_string()))
+            }
+            Some(_) => {
+                // half-open: let this request probe the host, and block the others until
+                // it completes or the cooldown passes again
+                state.opened_at = Some(Instant::now());
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Record the outcome of a request to `host`
+    pub(crate) fn record(&self, host: &str, failed: bool) {
+        let mut hosts = self.hosts.lock().unwrap();
+        if !failed {
+            hosts.remove(host);
+            return;
+        }
+
+        let state = hosts.entry(host.to_string()).or_default();
+        state.failures += 1;
+        if state.failures >= self.config.failure_threshold {
+            state.opened_at = Some(Instant::now());
+        }
+    }
+}
+
+/// The host of `url`, used to key the circuit breaker
+pub(crate) fn host_of(url: &str) -> String {
+    url::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_else(|| url.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::{Misbehaviour, MockLnUrlServer};
+    use crate::Builder;
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy {
+            jitter: false,
+            ..Default::default()
+        };
+        assert_eq!(policy.backoff(0), Duration::from_millis(250));
+        assert_eq!(policy.backoff(1), Duration::from_millis(500));
+        assert_eq!(policy.backoff(2), Duration::from_secs(1));
+        assert_eq!(policy.backoff(10), Duration::from_secs(5));
+        assert_eq!(policy.backoff(100), Duration::from_secs(5));
+
+        let policy = RetryPolicy::default();
+        for retry in 0..5 {
+            let backoff = policy.backoff(retry);
+            let max = RetryPolicy {
+                jitter: false,
+                ..Default::default()
+            }
+            .backoff(retry);
+            assert!(backoff >= max / 2 && backoff <= max);
+        }
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let policy = RetryPolicy {
+            jitter: false,
+            ..Default::default()
+        };
+        assert_eq!(retry_after(503, Some(" 2 ")), Some(Duration::from_secs(2)));
+        assert_eq!(retry_after(429, Some("0")), Some(Duration::ZERO));
+        assert_eq!(retry_after(500, Some("2")), None);
+        assert_eq!(
+            retry_after(503, Some("Wed, 21 Oct 2015 07:28:00 GMT")),
+            None
+        );
+        assert_eq!(retry_after(503, None), None);
+
+        assert_eq!(policy.delay(0, None), Some(Duration::from_millis(250)));
+        assert_eq!(
+            policy.delay(0, Some(Duration::from_secs(2))),
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(
+            policy.delay(3, Some(Duration::from_millis(1))),
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(policy.delay(0, Some(Duration::from_secs(6))), None);
+    }
+
+    #[test]
+    fn test_should_retry() {
+        let policy = RetryPolicy::default();
+        let unavailable = Error::HttpResponse(503);
+
+        assert!(policy.should_retry(RequestKind::Idempotent, 0, &unavailable));
+        assert!(!policy.should_retry(RequestKind::Idempotent, 3, &unavailable));
+        assert!(!policy.should_retry(RequestKind::Invoice, 0, &unavailable));
+        assert!(!policy.should_retry(RequestKind::Once, 0, &unavailable));
+        assert!(!policy.should_retry(RequestKind::Idempotent, 0, &Error::HttpResponse(404)));
+
+        let policy = RetryPolicy {
+            retry_invoice_requests: true,
+            ..Default::default()
+        };
+        assert!(policy.should_retry(RequestKind::Invoice, 0, &unavailable));
+    }
+
+    #[test]
+    fn test_circuit_breaker() {
+        let breaker = CircuitBreaker::new(CircuitBreakerConfig {
+            failure_threshold: 2,
+            cooldown: Duration::from_millis(50),
+        });
+
+        breaker.record("a.com", true);
+        assert!(breaker.check("a.com").is_ok());
+        breaker.record("a.com", true);
+        assert!(matches!(breaker.check("a.com"), Err(Error::CircuitOpen(host)) if host == "a.com"));
+        assert!(breaker.check("b.com").is_ok());
+
+        std::thread::sleep(Duration::from_millis(60));
+        // one probe is let through after the cooldown
+        assert!(breaker.check("a.com").is_ok());
+        assert!(breaker.check("a.com").is_err());
+
+        breaker.record("a.com", false);
+        assert!(breaker.check("a.com").is_ok());
+    }
+
+    /// A builder retrying twice and opening the circuit after four failures
+    fn builder() -> Builder {
+        let policy = RetryPolicy {
+            max_retries: 2,
+            initial_backoff: Duration::from_millis(1),
+            ..Default::default()
+        };
+        Builder::default()
+            .retry(policy)
+            .circuit_breaker(CircuitBreakerConfig {
+                failure_threshold: 4,
+                cooldown: Duration::from_secs(60),
+            })
+    }
+
+    #[cfg(feature = "blocking")]
+    #[test]
+    fn test_blocking_retry_and_circuit_breaker() {
+        let server = MockLnUrlServer::start().unwrap();
+        let pay = server.add_pay_request("/pay", 1_000, 1_000_000, "coffee");
+        server.set_misbehaviour(Some(Misbehaviour::HttpStatus(503)));
+        let client = builder().build_blocking().unwrap();
+
+        // first-level requests are retried, pay callbacks are not
+        assert!(matches!(
+            client.make_request(&server.url("/pay")),
+            Err(Error::HttpResponse(503))
+        ));
+        assert_eq!(server.requests().len(), 3);
+        assert!(client.get_invoice(&pay, 21_000, None, None).is_err());
+        assert_eq!(server.requests().len(), 4);
+
+        // the fourth failure opened the circuit
+        assert!(matches!(
+            client.make_request(&server.url("/pay")),
+            Err(Error::CircuitOpen(_))
+        ));
+        assert_eq!(server.requests().len(), 4);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_async_retry_and_circuit_breaker() {
+        let server = MockLnUrlServer::start().unwrap();
+        let pay = server.add_pay_request("/pay", 1_000, 1_000_000, "coffee");
+        server.set_misbehaviour(Some(Misbehaviour::HttpStatus(503)));
+        let client = builder().build_async().unwrap();
+
+        // first-level requests are retried, pay callbacks are not
+        assert!(matches!(
+            client.make_request(&server.url("/pay")).await,
+            Err(Error::Reqwest(ref e)) if e.status().map(|s| s.as_u16()) == Some(503)
+        ));
+        assert_eq!(server.requests().len(), 3);
+        assert!(client.get_invoice(&pay, 21_000, None, None).await.is_err());
+        assert_eq!(server.requests().len(), 4);
+
+        // the fourth failure opened the circuit
+        assert!(matches!(
+            client.make_request(&server.url("/pay")).await,
+            Err(Error::CircuitOpen(_))
+        ));
+        assert_eq!(server.requests().len(), 4);
+    }
+
+    #[cfg(feature = "blocking")]
+    #[test]
+    fn test_blocking_retry_after() {
+        let server = MockLnUrlServer::start().unwrap();
+        server.add_pay_request("/pay", 1_000, 1_000_000, "coffee");
+        server.set_misbehaviour(Some(Misbehaviour::HttpStatus(503)));
+        let client = Builder::default()
+            .retry(RetryPolicy {
+                max_retries: 1,
+                initial_backoff: Duration::from_millis(1),
+                ..Default::default()
+            })
+            .build_blocking()
+            .unwrap();
+
+        server.set_retry_after(Some(1));
+        let start = Instant::now();
+        assert!(client.make_request(&server.url("/pay")).is_err());
+        assert!(start.elapsed() >= Duration::from_secs(1));
+        assert_eq!(server.requests().len(), 2);
+
+        // longer than the maximum backoff
+        server.set_retry_after(Some(60));
+        assert!(client.make_request(&server.url("/pay")).is_err());
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_async_retry_after() {
+        let server = MockLnUrlServer::start().unwrap();
+        server.add_pay_request("/pay", 1_000, 1_000_000, "coffee");
+        server.set_misbehaviour(Some(Misbehaviour::HttpStatus(503)));
+        let client = Builder::default()
+            .retry(RetryPolicy {
+                max_retries: 1,
+                initial_backoff: Duration::from_millis(1),
+                ..Default::default()
+            })
+            .build_async()
+            .unwrap();
+
+        server.set_retry_after(Some(1));
+        let start = Instant::now();
+        assert!(client.make_request(&server.url("/pay")).await.is_err());
+        assert!(start.elapsed() >= Duration::from_secs(1));
+        assert_eq!(server.requests().len(), 2);
+
+        // longer than the maximum backoff
+        server.set_retry_after(Some(60));
+        assert!(client.make_request(&server.url("/pay")).await.is_err());
+        assert_eq!(server.requests().len(), 3);
+    }
+}
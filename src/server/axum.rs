@@ -0,0 +1,514 @@
+//! Ready-made [`axum`] routes for serving LNURL endpoints.
+
+use crate::bolt11::DecodedInvoice;
+use crate::pay::{LnURLPayInvoice, PayResponse};
+use crate::server::{HttpResponse, LightningAddressServer, UserRegistry};
+use crate::withdraw::WithdrawalResponse;
+use crate::{Error, Response};
+use ::axum::extract::{FromRequestParts, Path, Query, State};
+use ::axum::http::request::Parts;
+use ::axum::http::{header, StatusCode};
+use ::axum::response::IntoResponse;
+use ::axum::routing::get;
+use ::axum::Router;
+use async_trait::async_trait;
+use bitcoin::hashes::hex::FromHex;
+use bitcoin::secp256k1::ecdsa::Signature;
+use bitcoin::secp256k1::{Message, PublicKey, Secp256k1};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Serves the LNURL-pay requests mounted with [`LnUrlRouter::pay`].
+#[async_trait]
+pub trait PayBackend: Send + Sync + 'static {
+    /// The pay request for `id`, with a callback pointing at `<path>/<id>/callback`
+    async fn pay_response(&self, id: &str) -> Result<PayResponse, Error>;
+
+    /// Create an invoice for a callback request to `id`.
+    ///
+    /// The amount and comment have already been checked against [`PayBackend::pay_response`].
+    async fn get_invoice(
+        &self,
+        id: &str,
+        query: PayCallbackQuery,
+    ) -> Result<LnURLPayInvoice, Error>;
+}
+
+/// Serves the LNURL-withdraw requests mounted with [`LnUrlRouter::withdraw`].
+#[async_trait]
+pub trait WithdrawBackend: Send + Sync + 'static {
+    /// The withdraw request for `id`, with a callback pointing at `<path>/<id>/callback`
+    async fn withdraw_response(&self, id: &str) -> Result<WithdrawalResponse, Error>;
+
+    /// Pay the invoice of a callback request to `id`
+    async fn withdraw(&self, id: &str, query: WithdrawCallbackQuery) -> Result<(), Error>;
+}
+
+/// Serves the LNURL-auth requests mounted with [`LnUrlRouter::auth`].
+#[async_trait]
+pub trait AuthBackend: Send + Sync + 'static {
+    /// Log in the holder of `key` for the challenge `k1`.
+    ///
+    /// The signature of `k1` by `key` has already been verified. Implementations must check
+    /// that `k1` is a challenge they issued and that it has not been used before.
+    async fn authenticate(&self, query: AuthQuery) -> Result<(), Error>;
+}
+
+/// Query of an LNURL-pay callback request
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct PayCallbackQuery {
+    /// Amount requested, in millisatoshis
+    pub amount: u64,
+    /// Comment from the payer
+    pub comment: Option<String>,
+    /// Nostr zap request event, as JSON
+    pub nostr: Option<String>,
+}
+
+/// Query of an LNURL-withdraw callback request
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct WithdrawCallbackQuery {
+    /// The secret from the withdraw request
+    pub k1: String,
+    /// The invoice to pay
+    pub pr: String,
+}
+
+/// Query of an LNURL-auth login request
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct AuthQuery {
+    /// The hex encoded challenge
+    pub k1: String,
+    /// The optional action being authorized, one of `register`, `login`, `link` or `auth`
+    pub action: Option<String>,
+    /// Signature of `k1` by `key`
+    #[serde(deserialize_with = "deserialize_from_str")]
+    pub sig: Signature,
+    /// The linking key of the user
+    #[serde(deserialize_with = "deserialize_from_str")]
+    pub key: PublicKey,
+}
+
+impl AuthQuery {
+    /// Verify that `sig` is a signature of `k1` by `key`
+    pub fn verify(&self) -> Result<(), Error> {
+        let k1 =
+            <[u8; 32]>::from_hex(&self.k1).map_err(|_| Error::Auth("Invalid k1".to_string()))?;
+        let msg = Message::from_digest(k1);
+        Secp256k1::verification_only()
+            .verify_ecdsa(&msg, &self.sig, &self.key)
+            .map_err(|_| Error::Auth("Invalid signature".to_string()))
+    }
+}
+
+fn deserialize_from_str<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    let s = String::deserialize(deserializer)?;
+    T::from_str(&s).map_err(serde::de::Error::custom)
+}
+
+/// Builds an axum [`Router`] serving LNURL endpoints from user-implemented backends.
+///
+/// ```ignore
+/// let router = LnUrlRouter::new()
+///     .lightning_address(LightningAddressServer::new("example.com", registry))
+///     .pay("/lnurlp", pay_backend)
+///     .withdraw("/lnurlw", withdraw_backend)
+///     .auth("/auth", auth_backend)
+///     .build();
+/// ```
+#[derive(Debug, Default)]
+pub struct LnUrlRouter {
+    router: Router,
+}
+
+impl LnUrlRouter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serve `/.well-known/lnurlp/<username>` and `/.well-known/keysend/<username>` from a
+    /// [`LightningAddressServer`]
+    pub fn lightning_address<R>(self, server: LightningAddressServer<R>) -> Self
+    where
+        R: UserRegistry + Send + Sync + 'static,
+    {
+        let router = Router::new()
+            .route("/.well-known/lnurlp/:username", get(lightning_address::<R>))
+            .route("/.well-known/keysend/:username", get(keysend::<R>))
+            .with_state(Arc::new(server));
+        self.merge(router)
+    }
+
+    /// Serve pay requests at `<path>/<id>` and their callbacks at `<path>/<id>/callback`
+    pub fn pay<B: PayBackend>(self, path: &str, backend: B) -> Self {
+        let path = path.trim_end_matches('/');
+        let router = Router::new()
+            .route(&format!("{path}/:id"), get(pay_response::<B>))
+            .route(&format!("{path}/:id/callback"), get(pay_callback::<B>))
+            .with_state(Arc::new(backend));
+        self.merge(router)
+    }
+
+    /// Serve withdraw requests at `<path>/<id>` and their callbacks at `<path>/<id>/callback`
+    pub fn withdraw<B: WithdrawBackend>(self, path: &str, backend: B) -> Self {
+        let path = path.trim_end_matches('/');
+        let router = Router::new()
+            .route(&format!("{path}/:id"), get(withdraw_response::<B>))
+            .route(&format!("{path}/:id/callback"), get(withdraw_callback::<B>))
+            .with_state(Arc::new(backend));
+        self.merge(router)
+    }
+
+    /// Serve LNURL-auth logins at `path`
+    pub fn auth<B: AuthBackend>(self, path: &str, backend: B) -> Self {
+        let router = Router::new()
+            .route(path, get(auth::<B>))
+            .with_state(Arc::new(backend));
+        self.merge(router)
+    }
+
+    fn merge(self, router: Router) -> Self {
+        LnUrlRouter {
+            router: self.router.merge(router),
+        }
+    }
+
+    pub fn build(self) -> Router {
+        self.router
+    }
+}
+
+impl IntoResponse for HttpResponse {
+    fn into_response(self) -> ::axum::response::Response {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        (
+            status,
+            [(header::CONTENT_TYPE, "application/json")],
+            self.body,
+        )
+            .into_response()
+    }
+}
+
+/// A [`Query`] whose rejections are sent as LNURL error responses
+struct LnUrlQuery<T>(T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for LnUrlQuery<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = HttpResponse;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        match Query::<T>::from_request_parts(parts, state).await {
+            Ok(Query(query)) => Ok(LnUrlQuery(query)),
+            Err(rejection) => Err(HttpResponse::error(400, rejection.body_text())),
+        }
+    }
+}
+
+/// The status code to send along with an LNURL error body for `error`
+fn error_status(error: &Error) -> u16 {
+    match error {
+        Error::UnknownUser(_) => 404,
+        Error::InvalidAmount
+        | Error::AmountOutOfRange { .. }
+        | Error::InvalidComment
+        | Error::InvalidLightningAddress
+        | Error::InvalidInvoice(_)
+        | Error::InvalidField { .. }
+        | Error::Auth(_) => 400,
+        _ => 500,
+    }
+}
+
+fn into_response<T: serde::Serialize>(result: Result<T, Error>) -> HttpResponse {
+    match result {
+        Ok(value) => HttpResponse::ok(&value),
+        Err(e) => HttpResponse::error(error_status(&e), e.to_string()),
+    }
+}
+
+fn ok_or_error(result: Result<(), Error>) -> HttpResponse {
+    into_response(result.map(|_| Response::<()>::Ok(())))
+}
+
+async fn lightning_address<R: UserRegistry>(
+    State(server): State<Arc<LightningAddressServer<R>>>,
+    Path(username): Path<String>,
+) -> HttpResponse {
+    into_response(server.pay_response(&username))
+}
+
+async fn keysend<R: UserRegistry>(
+    State(server): State<Arc<LightningAddressServer<R>>>,
+    Path(username): Path<String>,
+) -> HttpResponse {
+    into_response(server.keysend_response(&username))
+}
+
+async fn pay_response<B: PayBackend>(
+    State(backend): State<Arc<B>>,
+    Path(id): Path<String>,
+) -> HttpResponse {
+    into_response(backend.pay_response(&id).await)
+}
+
+async fn pay_callback<B: PayBackend>(
+    State(backend): State<Arc<B>>,
+    Path(id): Path<String>,
+    LnUrlQuery(query): LnUrlQuery<PayCallbackQuery>,
+) -> HttpResponse {
+    let result = async {
+        let pay = backend.pay_response(&id).await?;
+        pay.check_amount(query.amount)?;
+        match (&query.comment, pay.comment_allowed) {
+            (Some(comment), Some(max)) if comment.len() > max as usize => {
+                return Err(Error::InvalidComment)
+            }
+            (Some(_), None) => return Err(Error::InvalidComment),
+            _ => {}
+        }
+        backend.get_invoice(&id, query).await
+    };
+    into_response(result.await)
+}
+
+async fn withdraw_response<B: WithdrawBackend>(
+    State(backend): State<Arc<B>>,
+    Path(id): Path<String>,
+) -> HttpResponse {
+    into_response(backend.withdraw_response(&id).await)
+}
+
+async fn withdraw_callback<B: WithdrawBackend>(
+    State(backend): State<Arc<B>>,
+    Path(id): Path<String>,
+    LnUrlQuery(query): LnUrlQuery<WithdrawCallbackQuery>,
+) -> HttpResponse {
+    let result = match DecodedInvoice::from_str(&query.pr) {
+        Ok(_) => backend.withdraw(&id, query).await,
+        Err(e) => Err(e),
+    };
+    ok_or_error(result)
+}
+
+async fn auth<B: AuthBackend>(
+    State(backend): State<Arc<B>>,
+    LnUrlQuery(query): LnUrlQuery<AuthQuery>,
+) -> HttpResponse {
+    let result = match query.verify() {
+        Ok(()) => backend.authenticate(query).await,
+        Err(e) => Err(e),
+    };
+    ok_or_error(result)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixtures::{pay_response, withdrawal, K1};
+    use ::axum::body::{to_bytes, Body};
+    use ::axum::http::Request;
+    use bitcoin::secp256k1::SecretKey;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use tower::ServiceExt;
+
+    const INVOICE: &str = "lnbc9678785340p1pwmna7lpp5gc3xfm08u9qy06djf8dfflhugl6p7lgza6dsjxq454gxhj9t7a0sd8dgfkx7cmtwd68yetpd5s9xar0wfjn5gpc8qhrsdfq24f5ggrxdaezqsnvda3kkum5wfjkzmfqf3jkgem9wgsyuctwdus9xgrcyqcjcgpzgfskx6eqf9hzqnteypzxz7fzypfhg6trddjhygrcyqezcgpzfysywmm5ypxxjemgw3hxjmn8yptk7untd9hxwg3q2d6xjcmtv4ezq7pqxgsxzmnyyqcjqmt0wfjjq6t5v4khxsp5zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zygsxqyjw5qcqp2rzjq0gxwkzc8w6323m55m4jyxcjwmy7stt9hwkwe2qxmy8zpsgg7jcuwz87fcqqeuqqqyqqqqlgqqqqn3qq9q9qrsgqrvgkpnmps664wgkp43l22qsgdw4ve24aca4nymnxddlnp8vh9v2sdxlu5ywdxefsfvm0fq3sesf08uf6q9a2ke0hc9j6z6wlxg5z5kqpu2v9wz";
+
+    struct TestPayBackend;
+
+    #[async_trait]
+    impl PayBackend for TestPayBackend {
+        async fn pay_response(&self, id: &str) -> Result<PayResponse, Error> {
+            match id {
+                "alice" => Ok(PayResponse {
+                    comment_allowed: Some(10),
+                    ..pay_response(r#"[["text/plain","test"]]"#)
+                }),
+                _ => Err(Error::UnknownUser(id.to_string())),
+            }
+        }
+
+        async fn get_invoice(
+            &self,
+            _id: &str,
+            query: PayCallbackQuery,
+        ) -> Result<LnURLPayInvoice, Error> {
+            assert_eq!(query.amount, 250_000_000);
+            Ok(LnURLPayInvoice::new(INVOICE.to_string()))
+        }
+    }
+
+    #[derive(Default)]
+    struct TestWithdrawBackend {
+        paid: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl WithdrawBackend for Arc<TestWithdrawBackend> {
+        async fn withdraw_response(&self, id: &str) -> Result<WithdrawalResponse, Error> {
+            Ok(WithdrawalResponse {
+                callback: format!("https://example.com/lnurlw/{id}/callback"),
+                ..withdrawal()
+            })
+        }
+
+        async fn withdraw(&self, _id: &str, query: WithdrawCallbackQuery) -> Result<(), Error> {
+            if query.k1 != K1 {
+                return Err(Error::Auth("Invalid k1".to_string()));
+            }
+            self.paid.lock().unwrap().push(query.pr);
+            Ok(())
+        }
+    }
+
+    struct TestAuthBackend;
+
+    #[async_trait]
+    impl AuthBackend for TestAuthBackend {
+        async fn authenticate(&self, query: AuthQuery) -> Result<(), Error> {
+            assert_eq!(query.action.as_deref(), Some("login"));
+            Ok(())
+        }
+    }
+
+    async fn get(router: &Router, uri: &str) -> (StatusCode, serde_json::Value) {
+        let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_lightning_address_routes() {
+        let mut registry = HashMap::new();
+        registry.insert(
+            "alice".to_string(),
+            pay_response(r#"[["text/plain","test"]]"#),
+        );
+        let router = LnUrlRouter::new()
+            .lightning_address(LightningAddressServer::new("example.com", registry))
+            .build();
+
+        let (status, body) = get(&router, "/.well-known/lnurlp/alice").await;
+        assert_eq!(status, StatusCode::OK);
+        let pay: PayResponse = serde_json::from_value(body).unwrap();
+        assert_eq!(
+            pay.decoded_metadata().unwrap().identifier(),
+            Some("alice@example.com")
+        );
+
+        let (status, _) = get(&router, "/.well-known/lnurlp/bob").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, _) = get(&router, "/.well-known/keysend/alice").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_pay_routes() {
+        let router = LnUrlRouter::new().pay("/lnurlp", TestPayBackend).build();
+
+        let (status, body) = get(&router, "/lnurlp/alice").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            serde_json::from_value::<PayResponse>(body).unwrap(),
+            TestPayBackend.pay_response("alice").await.unwrap()
+        );
+
+        let (status, body) = get(&router, "/lnurlp/alice/callback?amount=250000000").await;
+        assert_eq!(status, StatusCode::OK);
+        let invoice: LnURLPayInvoice = serde_json::from_value(body).unwrap();
+        assert_eq!(invoice.invoice(), INVOICE);
+
+        let (status, _) = get(&router, "/lnurlp/alice/callback?amount=1").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, body) = get(&router, "/lnurlp/alice/callback").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["status"], "ERROR");
+
+        let (status, _) = get(
+            &router,
+            "/lnurlp/alice/callback?amount=250000000&comment=far%20too%20long",
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, _) = get(&router, "/lnurlp/bob").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_withdraw_routes() {
+        let backend = Arc::new(TestWithdrawBackend::default());
+        let router = LnUrlRouter::new()
+            .withdraw("/lnurlw/", backend.clone())
+            .build();
+
+        let (status, body) = get(&router, "/lnurlw/abc").await;
+        assert_eq!(status, StatusCode::OK);
+        let withdraw: WithdrawalResponse = serde_json::from_value(body).unwrap();
+        assert_eq!(withdraw.k1, K1);
+
+        let uri = format!("/lnurlw/abc/callback?k1={K1}&pr={INVOICE}");
+        let (status, body) = get(&router, &uri).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, serde_json::json!({"status": "OK"}));
+        assert_eq!(*backend.paid.lock().unwrap(), vec![INVOICE.to_string()]);
+
+        let uri = format!("/lnurlw/abc/callback?k1=00&pr={INVOICE}");
+        let (status, body) = get(&router, &uri).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["status"], "ERROR");
+
+        let uri = format!("/lnurlw/abc/callback?k1={K1}&pr=lnbc");
+        let (status, body) = get(&router, &uri).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["status"], "ERROR");
+
+        let (status, body) = get(&router, &format!("/lnurlw/abc/callback?k1={K1}")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["status"], "ERROR");
+        assert_eq!(backend.paid.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_auth_route() {
+        let router = LnUrlRouter::new().auth("/auth", TestAuthBackend).build();
+
+        let secp = Secp256k1::new();
+        let secret_key = SecretKey::from_slice(&[0x42; 32]).unwrap();
+        let key = PublicKey::from_secret_key(&secp, &secret_key);
+        let msg = Message::from_digest(<[u8; 32]>::from_hex(K1).unwrap());
+        let sig = secp.sign_ecdsa(&msg, &secret_key);
+
+        let uri = format!("/auth?tag=login&action=login&k1={K1}&sig={sig}&key={key}");
+        let (status, body) = get(&router, &uri).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, serde_json::json!({"status": "OK"}));
+
+        let other_k1 = "00".repeat(32);
+        let uri = format!("/auth?tag=login&action=login&k1={other_k1}&sig={sig}&key={key}");
+        let (status, body) = get(&router, &uri).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["status"], "ERROR");
+
+        let uri = format!("/auth?tag=login&k1={K1}&sig=nope&key={key}");
+        let (status, body) = get(&router, &uri).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["status"], "ERROR");
+    }
+}
@@ -0,0 +1,278 @@
+use crate::keysend::KeysendResponse;
+use crate::lightning_address::LightningAddress;
+use crate::pay::PayResponse;
+use crate::server::HttpResponse;
+use crate::Error;
+use percent_encoding::percent_decode_str;
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+/// Path prefix lightning addresses are served under, per LUD-16
+pub const LNURLP_PATH_PREFIX: &str = "/.well-known/lnurlp/";
+
+/// Path prefix the keysend details of lightning addresses are served under
+pub const KEYSEND_PATH_PREFIX: &str = "/.well-known/keysend/";
+
+/// Registry of the users a [`LightningAddressServer`] hosts addresses for.
+pub trait UserRegistry {
+    /// Return the pay configuration for `username`, or `None` if the user is unknown.
+    ///
+    /// `tag` is the optional `+tag` sub-address, e.g. `tips` for `alice+tips@domain.com`.
+    /// The `text/identifier` metadata entry is inserted by the server and does not need
+    /// to be included in the returned metadata.
+    fn pay_response(&self, username: &str, tag: Option<&str>) -> Option<PayResponse>;
+
+    /// Return the keysend details for `username`, or `None` if the user does not accept
+    /// keysend payments. No user does by default.
+    fn keysend_response(&self, _username: &str, _tag: Option<&str>) -> Option<KeysendResponse> {
+        None
+    }
+}
+
+impl UserRegistry for HashMap<String, PayResponse> {
+    fn pay_response(&self, username: &str, _tag: Option<&str>) -> Option<PayResponse> {
+        self.get(username).cloned()
+    }
+}
+
+/// Serves `/.well-known/lnurlp/<username>` and `/.well-known/keysend/<username>` requests for
+/// the users of a [`UserRegistry`].
+#[derive(Debug, Clone)]
+pub struct LightningAddressServer<R> {
+    domain: String,
+    registry: R,
+}
+
+impl<R: UserRegistry> LightningAddressServer<R> {
+    /// Create a server for the lightning addresses `<username>@domain` of the users of `registry`
+    pub fn new(domain: &str, registry: R) -> Self {
+        LightningAddressServer {
+            domain: domain.to_string(),
+            registry,
+        }
+    }
+
+    /// The domain the lightning addresses are hosted on
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+
+    /// The registry of the users hosted by this server
+    pub fn registry(&self) -> &R {
+        &self.registry
+    }
+
+    /// Build the [`PayResponse`] for the local part of one of our lightning addresses.
+    ///
+    /// The local part is normalised to lowercase, and a local part of the form `user+tag` is
+    /// looked up as `user` with the sub-address `tag`.
+    /// The metadata of the returned response always contains a `text/identifier` entry
+    /// equal to the full lightning address, as required by LUD-16.
+    pub fn pay_response(&self, local_part: &str) -> Result<PayResponse, Error> {
+        let address = LightningAddress::from_domain_and_local_part(&self.domain, local_part)?;
+        let (username, tag) = split_sub_address(&address);
+
+        let mut pay = self
+            .registry
+            .pay_response(username, tag)
+            .ok_or_else(|| Error::UnknownUser(username.to_string()))?;
+        pay.metadata = insert_identifier(&pay.metadata, &address)?;
+
+        Ok(pay)
+    }
+
+    /// Build the [`KeysendResponse`] for the local part of one of our lightning addresses,
+    /// looked up like [`LightningAddressServer::pay_response`].
+    ///
+    /// Fails with [`Error::UnknownUser`] if the user does not accept keysend payments.
+    pub fn keysend_response(&self, local_part: &str) -> Result<KeysendResponse, Error> {
+        let address = LightningAddress::from_domain_and_local_part(&self.domain, local_part)?;
+        let (username, tag) = split_sub_address(&address);
+
+        self.registry
+            .keysend_response(username, tag)
+            .ok_or_else(|| Error::UnknownUser(username.to_string()))
+    }
+
+    /// Handle a request for `path`, returning the JSON response to send back.
+    ///
+    /// The local part is percent-decoded from `path`, ignoring any query string. Returns `None`
+    /// if `path` is not a lightning address or keysend path.
+    pub fn handle(&self, path: &str) -> Option<HttpResponse> {
+        let path = path.split_once('?').map_or(path, |(path, _query)| path);
+        let result = if let Some(local_part) = path.strip_prefix(LNURLP_PATH_PREFIX) {
+            decode_local_part(local_part)
+                .and_then(|local_part| self.pay_response(&local_part))
+                .map(|pay| HttpResponse::ok(&pay))
+        } else {
+            let local_part = path.strip_prefix(KEYSEND_PATH_PREFIX)?;
+            decode_local_part(local_part)
+                .and_then(|local_part| self.keysend_response(&local_part))
+                .map(|keysend| HttpResponse::ok(&keysend))
+        };
+
+        let response = match result {
+            Ok(response) => response,
+            Err(Error::UnknownUser(username)) => {
+                HttpResponse::error(404, format!("Unknown user: {username}"))
+            }
+            Err(Error::InvalidLightningAddress) => {
+                HttpResponse::error(400, "Invalid lightning address")
+            }
+            Err(e) => HttpResponse::error(500, e.to_string()),
+        };
+
+        Some(response)
+    }
+}
+
+/// Percent-decode the local part of a request path
+fn decode_local_part(local_part: &str) -> Result<Cow<'_, str>, Error> {
+    percent_decode_str(local_part)
+        .decode_utf8()
+        .map_err(|_| Error::InvalidLightningAddress)
+}
+
+/// Split the local part of `address` into the username and the optional `+tag` sub-address
+fn split_sub_address(address: &LightningAddress) -> (&str, Option<&str>) {
+    match address.local_part().split_once('+') {
+        Some((username, tag)) => (username, Some(tag)),
+        None => (address.local_part(), None),
+    }
+}
+
+/// Replace any `text/identifier` entry in `metadata` with one for `address`.
+fn insert_identifier(metadata: &str, address: &LightningAddress) -> Result<String, Error> {
+    let mut entries: Vec<serde_json::Value> = if metadata.is_empty() {
+        vec![]
+    } else {
+        serde_json::from_str(metadata)?
+    };
+
+    entries.retain(|entry| entry.get(0).and_then(|t| t.as_str()) != Some("text/identifier"));
+    entries.push(serde_json::json!(["text/identifier", address.to_string()]));
+
+    Ok(serde_json::to_string(&entries)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixtures::pay_response;
+    use crate::Tag;
+
+    fn server() -> LightningAddressServer<HashMap<String, PayResponse>> {
+        let mut registry = HashMap::new();
+        registry.insert(
+            "alice".to_string(),
+            pay_response(r#"[["text/plain","Pay alice"]]"#),
+        );
+        LightningAddressServer::new("example.com", registry)
+    }
+
+    #[test]
+    fn test_inserts_identifier() {
+        let pay = server().pay_response("alice").unwrap();
+        assert_eq!(
+            pay.metadata_json(),
+            serde_json::json!([
+                ["text/plain", "Pay alice"],
+                ["text/identifier", "alice@example.com"]
+            ])
+        );
+    }
+
+    #[test]
+    fn test_replaces_identifier() {
+        let mut registry = HashMap::new();
+        registry.insert(
+            "bob".to_string(),
+            pay_response(r#"[["text/identifier","mallory@evil.com"]]"#),
+        );
+        let server = LightningAddressServer::new("example.com", registry);
+
+        let pay = server.pay_response("bob").unwrap();
+        assert_eq!(
+            pay.metadata_json(),
+            serde_json::json!([["text/identifier", "bob@example.com"]])
+        );
+    }
+
+    #[test]
+    fn test_sub_address() {
+        let pay = server().pay_response("alice+tips").unwrap();
+        assert_eq!(
+            pay.metadata_json(),
+            serde_json::json!([
+                ["text/plain", "Pay alice"],
+                ["text/identifier", "alice+tips@example.com"]
+            ])
+        );
+    }
+
+    #[test]
+    fn test_handle() {
+        let server = server();
+
+        let resp = server.handle("/.well-known/lnurlp/alice").unwrap();
+        assert_eq!(resp.status, 200);
+        let pay: PayResponse = serde_json::from_str(&resp.body).unwrap();
+        assert_eq!(pay.callback, "https://example.com/callback");
+
+        let resp = server.handle("/.well-known/lnurlp/%41lice").unwrap();
+        assert_eq!(resp.status, 200);
+        let resp = server
+            .handle("/.well-known/lnurlp/alice?amount=1000")
+            .unwrap();
+        assert_eq!(resp.status, 200);
+
+        let resp = server.handle("/.well-known/lnurlp/carol").unwrap();
+        assert_eq!(resp.status, 404);
+        assert_eq!(
+            serde_json::from_str::<crate::Response<()>>(&resp.body).unwrap(),
+            crate::Response::Error {
+                reason: "Unknown user: carol".to_string()
+            }
+        );
+
+        assert!(server.handle("/lnurlp/alice").is_none());
+    }
+
+    struct KeysendRegistry;
+
+    impl UserRegistry for KeysendRegistry {
+        fn pay_response(&self, _username: &str, _tag: Option<&str>) -> Option<PayResponse> {
+            None
+        }
+
+        fn keysend_response(&self, username: &str, tag: Option<&str>) -> Option<KeysendResponse> {
+            let pubkey = "030a58b8653d32b99200a2334cfe913e51dc7d155aa0116c176657a4f1722677a3";
+            let keysend = KeysendResponse::new(pubkey.parse().unwrap());
+            match (username, tag) {
+                ("alice", None) => Some(keysend.custom_record(696969, "alice")),
+                ("alice", Some(tag)) => Some(keysend.custom_record(696969, format!("alice+{tag}"))),
+                _ => None,
+            }
+        }
+    }
+
+    #[test]
+    fn test_handle_keysend() {
+        let keysend_server = LightningAddressServer::new("example.com", KeysendRegistry);
+
+        let resp = keysend_server
+            .handle("/.well-known/keysend/alice+tips")
+            .unwrap();
+        assert_eq!(resp.status, 200);
+        let keysend: KeysendResponse = serde_json::from_str(&resp.body).unwrap();
+        assert_eq!(keysend.tag, Tag::Keysend);
+        assert_eq!(keysend.custom_data[0].custom_value, "alice+tips");
+
+        let resp = keysend_server.handle("/.well-known/keysend/bob").unwrap();
+        assert_eq!(resp.status, 404);
+
+        // registries don't serve keysend unless they opt in
+        let resp = server().handle("/.well-known/keysend/alice").unwrap();
+        assert_eq!(resp.status, 404);
+    }
+}
@@ -0,0 +1,43 @@
+//! Server-side helpers for hosting LNURL services.
+
+#[cfg(feature = "axum")]
+pub mod axum;
+pub mod lightning_address;
+
+pub use lightning_address::{LightningAddressServer, UserRegistry};
+
+use crate::Response;
+use serde::Serialize;
+
+/// A framework-agnostic HTTP response produced by the server handlers.
+///
+/// `body` is always a JSON document, either the requested LNURL object or an
+/// LNURL error of the form `{"status":"ERROR","reason":"..."}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpResponse {
+    /// HTTP status code
+    pub status: u16,
+    /// JSON encoded body
+    pub body: String,
+}
+
+impl HttpResponse {
+    /// A `200 OK` response with `value` as the JSON body
+    pub fn ok<T: Serialize>(value: &T) -> Self {
+        match serde_json::to_string(value) {
+            Ok(body) => HttpResponse { status: 200, body },
+            Err(e) => HttpResponse::error(500, e.to_string()),
+        }
+    }
+
+    /// An LNURL error response with the given status code and reason
+    pub fn error(status: u16, reason: impl Into<String>) -> Self {
+        let response = Response::<()>::Error {
+            reason: reason.into(),
+        };
+        HttpResponse {
+            status,
+            body: serde_json::to_string(&response).expect("error response serializes"),
+        }
+    }
+}
@@ -0,0 +1,813 @@
+//! A local mock LNURL service for integration tests.
+//!
+//! [`MockLnUrlServer`] listens on `127.0.0.1` and serves pay, withdraw, channel, keysend and auth
+//! requests, answering pay callbacks with real signed regtest invoices from a
+//! [`MockLightningBackend`]. It can be told to misbehave to exercise a client's error
+//! handling, see [`Misbehaviour`].
+
+use crate::backend::mock::MockLightningBackend;
+use crate::backend::InvoiceDescription;
+use crate::bolt11;
+use crate::channel::ChannelResponse;
+use crate::keysend::KeysendResponse;
+use crate::lightning_address::LightningAddress;
+use crate::lnurl::LnUrl;
+use crate::pay::{LnURLPayInvoice, PayResponse};
+use crate::server::lightning_address::{KEYSEND_PATH_PREFIX, LNURLP_PATH_PREFIX};
+use crate::server::HttpResponse;
+use crate::withdraw::WithdrawalResponse;
+use crate::{Error, Tag};
+use bitcoin::hashes::hex::FromHex;
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::hex::DisplayHex;
+use bitcoin::secp256k1::ecdsa::Signature;
+use bitcoin::secp256k1::rand::random;
+use bitcoin::secp256k1::{Message, PublicKey, Secp256k1};
+use bitcoin::Network;
+use serde_json::json;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Path suffix of the callbacks of the requests served by a [`MockLnUrlServer`]
+const CALLBACK_SUFFIX: &str = "/callback";
+
+/// Ways a [`MockLnUrlServer`] can misbehave
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Misbehaviour {
+    /// Answer pay callbacks with an invoice for a different amount than requested
+    WrongAmount,
+    /// Answer pay callbacks with an invoice that does not commit to the metadata
+    WrongDescriptionHash,
+    /// Answer requests and their callbacks with an LNURL error with this reason
+    ErrorResponse(String),
+    /// Answer pay callbacks with `{"status":"OK"}` instead of an invoice
+    MissingInvoice,
+    /// Answer every request with this HTTP status code
+    HttpStatus(u16),
+    /// Wait this long before answering each request
+    Slow(Duration),
+}
+
+#[derive(Debug, Clone)]
+enum Endpoint {
+    Pay(PayResponse),
+    Withdraw(WithdrawalResponse),
+    Channel(ChannelResponse),
+    Keysend(KeysendResponse),
+    Auth { k1: String },
+}
+
+#[derive(Debug, Default)]
+struct State {
+    endpoints: HashMap<String, Endpoint>,
+    misbehaviour: Option<Misbehaviour>,
+    cache_control: Option<String>,
+    without_etag: bool,
+    retry_after: Option<u64>,
+    requests: Vec<String>,
+    request_headers: Vec<HashMap<String, String>>,
+    withdrawals: Vec<String>,
+    channels: Vec<(PublicKey, bool)>,
+    logins: Vec<PublicKey>,
+}
+
+/// A mock LNURL service listening on a random local port.
+///
+/// The server stops when it is dropped.
+#[derive(Debug)]
+pub struct MockLnUrlServer {
+    addr: SocketAddr,
+    base_url: String,
+    node: Arc<MockLightningBackend>,
+    state: Arc<Mutex<State>>,
+    shutdown: Arc<AtomicBool>,
+}
+
+impl MockLnUrlServer {
+    /// Start a server backed by a new regtest [`MockLightningBackend`]
+    pub fn start() -> Result<Self, Error> {
+        Self::with_node(MockLightningBackend::new(Network::Regtest))
+    }
+
+    /// Start a server creating its invoices with `node`
+    pub fn with_node(node: MockLightningBackend) -> Result<Self, Error> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+
+        let server = MockLnUrlServer {
+            addr,
+            base_url: format!("http://{addr}"),
+            node: Arc::new(node),
+            state: Arc::new(Mutex::new(State::default())),
+            shutdown: Arc::new(AtomicBool::new(false)),
+        };
+
+        let handler = Handler {
+            base_url: server.base_url.clone(),
+            node: server.node.clone(),
+            state: server.state.clone(),
+        };
+        let shutdown = server.shutdown.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if shutdown.load(Ordering::SeqCst) {
+                    break;
+                }
+                if let Ok(stream) = stream {
+                    let handler = handler.clone();
+                    thread::spawn(move || handler.serve(stream));
+                }
+            }
+        });
+
+        Ok(server)
+    }
+
+    /// The base URL of the server, e.g. `http://127.0.0.1:41234`
+    ///
+    /// Use it with [`crate::Builder::resolve`] to serve a lightning address domain.
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// The full URL of `path` on this server
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    /// The node creating this server's invoices
+    pub fn node(&self) -> &MockLightningBackend {
+        &self.node
+    }
+
+    /// Serve `pay` at `path`.
+    ///
+    /// Callbacks are answered at `{path}/callback`, which `pay.callback` should point to.
+    pub fn add_pay_response(&self, path: &str, pay: PayResponse) {
+        self.add_endpoint(path, Endpoint::Pay(pay));
+    }
+
+    /// Serve a pay request at `path` accepting `min_sendable` to `max_sendable` msats,
+    /// allowing comments and zaps, and return it
+    pub fn add_pay_request(
+        &self,
+        path: &str,
+        min_sendable: u64,
+        max_sendable: u64,
+        description: &str,
+    ) -> PayResponse {
+        let metadata = json!([["text/plain", description]]).to_string();
+        self.add_pay(path, min_sendable, max_sendable, metadata)
+    }
+
+    /// Serve a pay request for `address` at its LUD-16 path and return it.
+    ///
+    /// The server can then be reached through the address by building the client with
+    /// [`crate::Builder::resolve`] for the address' domain.
+    pub fn add_lightning_address(&self, address: &LightningAddress) -> PayResponse {
+        let path = format!("{LNURLP_PATH_PREFIX}{}", address.local_part());
+        let metadata = json!([
+            ["text/plain", format!("Payment to {address}")],
+            ["text/identifier", address.to_string()],
+        ])
+        .to_string();
+        self.add_pay(&path, 1_000, 100_000_000_000, metadata)
+    }
+
+    /// Serve keysend details for `address` at its keysend path, paying this server's node,
+    /// and return them
+    pub fn add_keysend(&self, address: &LightningAddress) -> KeysendResponse {
+        let path = format!("{KEYSEND_PATH_PREFIX}{}", address.local_part());
+        let keysend =
+            KeysendResponse::new(self.node.node_id()).custom_record(696969, address.local_part());
+        self.add_endpoint(&path, Endpoint::Keysend(keysend.clone()));
+        keysend
+    }
+
+    fn add_pay(
+        &self,
+        path: &str,
+        min_sendable: u64,
+        max_sendable: u64,
+        metadata: String,
+    ) -> PayResponse {
+        let pay = PayResponse {
+            callback: self.url(&format!("{path}{CALLBACK_SUFFIX}")),
+            max_sendable,
+            min_sendable,
+            tag: Tag::PayRequest,
+            metadata,
+            comment_allowed: Some(255),
+            allows_nostr: Some(true),
+            nostr_pubkey: None,
+            extra: Default::default(),
+        };
+        self.add_pay_response(path, pay.clone());
+        pay
+    }
+
+    /// Serve a withdraw request at `path` for up to `max_withdrawable` msats and return it
+    pub fn add_withdraw_request(&self, path: &str, max_withdrawable: u64) -> WithdrawalResponse {
+        let withdrawal = WithdrawalResponse {
+            default_description: "mock withdrawal".to_string(),
+            callback: self.url(&format!("{path}{CALLBACK_SUFFIX}")),
+            k1: random_k1(),
+            max_withdrawable,
+            min_withdrawable: Some(1_000),
+            tag: Tag::WithdrawRequest,
+            extra: Default::default(),
+        };
+        self.add_endpoint(path, Endpoint::Withdraw(withdrawal.clone()));
+        withdrawal
+    }
+
+    /// Serve a channel request at `path` and return it
+    pub fn add_channel_request(&self, path: &str) -> ChannelResponse {
+        let channel = ChannelResponse {
+            uri: format!("{}@{}", self.node.node_id(), self.addr),
+            callback: self.url(&format!("{path}{CALLBACK_SUFFIX}")),
+            k1: random_k1(),
+            tag: Tag::ChannelRequest,
+            extra: Default::default(),
+        };
+        self.add_endpoint(path, Endpoint::Channel(channel.clone()));
+        channel
+    }
+
+    /// Serve an LNURL-auth challenge at `path` and return its LNURL
+    pub fn add_auth(&self, path: &str) -> LnUrl {
+        let k1 = random_k1();
+        let url = self.url(&format!("{path}?tag=login&k1={k1}"));
+        self.add_endpoint(path, Endpoint::Auth { k1 });
+        LnUrl::from_url(url).expect("server urls are valid")
+    }
+
+    fn add_endpoint(&self, path: &str, endpoint: Endpoint) {
+        self.state
+            .lock()
+            .unwrap()
+            .endpoints
+            .insert(path.to_string(), endpoint);
+    }
+
+    /// Make the server misbehave, or behave again with `None`
+    pub fn set_misbehaviour(&self, misbehaviour: Option<Misbehaviour>) {
+        self.state.lock().unwrap().misbehaviour = misbehaviour;
+    }
+
+    /// Send this `Cache-Control` header, along with an `ETag`, with the first-level
+    /// responses, and answer requests revalidating them with `304 Not Modified`
+    pub fn set_cache_control(&self, cache_control: Option<&str>) {
+        self.state.lock().unwrap().cache_control = cache_control.map(str::to_string);
+    }
+
+    /// Whether to send an `ETag` along with the `Cache-Control` header, which it does by default
+    pub fn set_etag(&self, etag: bool) {
+        self.state.lock().unwrap().without_etag = !etag;
+    }
+
+    /// Send this `Retry-After` header, in seconds, with `429` and `503` responses
+    pub fn set_retry_after(&self, secs: Option<u64>) {
+        self.state.lock().unwrap().retry_after = secs;
+    }
+
+    /// The path and query of every request received, in order
+    pub fn requests(&self) -> Vec<String> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// The headers of the requests received so far, in order, with lowercase names
+    pub fn request_headers(&self) -> Vec<HashMap<String, String>> {
+        self.state.lock().unwrap().request_headers.clone()
+    }
+
+    /// The invoices submitted to withdraw callbacks, in order
+    pub fn withdrawals(&self) -> Vec<String> {
+        self.state.lock().unwrap().withdrawals.clone()
+    }
+
+    /// The node ids and privacy flags submitted to channel callbacks, in order
+    pub fn channels(&self) -> Vec<(PublicKey, bool)> {
+        self.state.lock().unwrap().channels.clone()
+    }
+
+    /// The keys that successfully logged in with LNURL-auth, in order
+    pub fn logins(&self) -> Vec<PublicKey> {
+        self.state.lock().unwrap().logins.clone()
+    }
+}
+
+impl Drop for MockLnUrlServer {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        // wake up the accept loop so it sees the shutdown flag
+        let _ = TcpStream::connect(self.addr);
+    }
+}
+
+fn random_k1() -> String {
+    random::<[u8; 32]>().to_lower_hex_string()
+}
+
+#[derive(Debug, Clone)]
+struct Handler {
+    base_url: String,
+    node: Arc<MockLightningBackend>,
+    state: Arc<Mutex<State>>,
+}
+
+impl Handler {
+    fn serve(&self, mut stream: TcpStream) {
+        let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+
+        let (target, headers) = match read_request(&stream) {
+            Some(request) => request,
+            None => return,
+        };
+        self.state
+            .lock()
+            .unwrap()
+            .request_headers
+            .push(headers.clone());
+        let mut response = self.handle(&target);
+
+        let mut extra_headers = String::new();
+        let (cache_control, without_etag, retry_after) = {
+            let state = self.state.lock().unwrap();
+            (
+                state.cache_control.clone(),
+                state.without_etag,
+                state.retry_after,
+            )
+        };
+        if let (Some(cache_control), true) = (cache_control, response.status == 200) {
+            if self.is_first_level(&target) {
+                extra_headers = format!("Cache-Control: {cache_control}\r\n");
+                if !without_etag {
+                    let etag = format!(
+                        "\"{}\"",
+                        &sha256::Hash::hash(response.body.as_bytes()).to_string()[..16]
+                    );
+                    if headers.get("if-none-match") == Some(&etag) {
+                        response = HttpResponse {
+                            status: 304,
+                            body: String::new(),
+                        };
+                    }
+                    extra_headers.push_str(&format!("ETag: {etag}\r\n"));
+                }
+            }
+        }
+
+        if let (Some(secs), 429 | 503) = (retry_after, response.status) {
+            extra_headers.push_str(&format!("Retry-After: {secs}\r\n"));
+        }
+
+        let reason = if response.status < 400 { "OK" } else { "ERROR" };
+        let _ = write!(
+            stream,
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n{}",
+            response.status,
+            reason,
+            response.body.len(),
+            extra_headers,
+            response.body
+        );
+        let _ = stream.flush();
+    }
+
+    /// Whether `target` is one of the pay, withdraw or channel requests
+    fn is_first_level(&self, target: &str) -> bool {
+        let path = target.split('?').next().unwrap_or_default();
+        matches!(
+            self.state.lock().unwrap().endpoints.get(path),
+            Some(Endpoint::Pay(_) | Endpoint::Withdraw(_) | Endpoint::Channel(_))
+        )
+    }
+
+    fn handle(&self, target: &str) -> HttpResponse {
+        let misbehaviour = {
+            let mut state = self.state.lock().unwrap();
+            state.requests.push(target.to_string());
+            state.misbehaviour.clone()
+        };
+
+        match &misbehaviour {
+            Some(Misbehaviour::Slow(delay)) => thread::sleep(*delay),
+            Some(Misbehaviour::HttpStatus(status)) => {
+                return HttpResponse::error(*status, "mock failure")
+            }
+            _ => {}
+        }
+
+        let url = match url::Url::parse(&format!("{}{}", self.base_url, target)) {
+            Ok(url) => url,
+            Err(e) => return HttpResponse::error(400, e.to_string()),
+        };
+        let query: HashMap<String, String> = url.query_pairs().into_owned().collect();
+        let path = url.path();
+
+        let (endpoint, callback_endpoint) = {
+            let state = self.state.lock().unwrap();
+            let callback_endpoint = path
+                .strip_suffix(CALLBACK_SUFFIX)
+                .and_then(|path| state.endpoints.get(path).cloned());
+            (state.endpoints.get(path).cloned(), callback_endpoint)
+        };
+
+        if let Some(Misbehaviour::ErrorResponse(reason)) = &misbehaviour {
+            if endpoint.is_some() || callback_endpoint.is_some() {
+                return HttpResponse::error(200, reason.clone());
+            }
+        }
+
+        if let Some(endpoint) = endpoint {
+            return match endpoint {
+                Endpoint::Pay(pay) => HttpResponse::ok(&pay),
+                Endpoint::Withdraw(withdrawal) => HttpResponse::ok(&withdrawal),
+                Endpoint::Channel(channel) => HttpResponse::ok(&channel),
+                Endpoint::Keysend(keysend) => HttpResponse::ok(&keysend),
+                Endpoint::Auth { k1 } => self.login(&k1, &query),
+            };
+        }
+
+        match callback_endpoint {
+            Some(Endpoint::Pay(pay)) => self.pay_callback(&pay, &query, &misbehaviour),
+            Some(Endpoint::Withdraw(withdrawal)) => self.withdraw_callback(&withdrawal, &query),
+            Some(Endpoint::Channel(channel)) => self.channel_callback(&channel, &query),
+            _ => HttpResponse::error(404, "not found"),
+        }
+    }
+
+    fn pay_callback(
+        &self,
+        pay: &PayResponse,
+        query: &HashMap<String, String>,
+        misbehaviour: &Option<Misbehaviour>,
+    ) -> HttpResponse {
+        let msats = match query.get("amount").and_then(|a| a.parse::<u64>().ok()) {
+            Some(msats) => msats,
+            None => return HttpResponse::error(400, "missing amount"),
+        };
+        if let Err(e) = pay.check_amount(msats) {
+            return HttpResponse::error(400, e.to_string());
+        }
+        if let Some(comment) = query.get("comment") {
+            if comment.len() > pay.comment_allowed.unwrap_or(0) as usize {
+                return HttpResponse::error(400, "comment too long");
+            }
+        }
+
+        // zap invoices commit to the zap request instead of the metadata, per NIP-57
+        let description = query.get("nostr").unwrap_or(&pay.metadata).clone();
+        let (msats, description) = match misbehaviour {
+            Some(Misbehaviour::WrongAmount) => (msats + 1_000, description),
+            Some(Misbehaviour::WrongDescriptionHash) => (msats, "[]".to_string()),
+            Some(Misbehaviour::MissingInvoice) => {
+                return HttpResponse::ok(&json!({ "status": "OK" }))
+            }
+            _ => (msats, description),
+        };
+        let invoice =
+            self.node
+                .create_invoice_sync(msats, &InvoiceDescription::Hash(description), None);
+
+        HttpResponse::ok(&LnURLPayInvoice::new(invoice.bolt11))
+    }
+
+    fn withdraw_callback(
+        &self,
+        withdrawal: &WithdrawalResponse,
+        query: &HashMap<String, String>,
+    ) -> HttpResponse {
+        if query.get("k1") != Some(&withdrawal.k1) {
+            return HttpResponse::error(400, "invalid k1");
+        }
+        let pr = match query.get("pr") {
+            Some(pr) => pr,
+            None => return HttpResponse::error(400, "missing invoice"),
+        };
+
+        let msats = match bolt11::parse_amount_msats(pr) {
+            Ok(Some(msats)) => msats,
+            _ => return HttpResponse::error(400, "invalid invoice"),
+        };
+        let min = withdrawal.min_withdrawable.unwrap_or(1);
+        if msats < min || msats > withdrawal.max_withdrawable {
+            return HttpResponse::error(400, "invalid amount");
+        }
+
+        self.state.lock().unwrap().withdrawals.push(pr.clone());
+        HttpResponse::ok(&json!({ "status": "OK" }))
+    }
+
+    fn channel_callback(
+        &self,
+        channel: &ChannelResponse,
+        query: &HashMap<String, String>,
+    ) -> HttpResponse {
+        if query.get("k1") != Some(&channel.k1) {
+            return HttpResponse::error(400, "invalid k1");
+        }
+        let node_id = match query.get("remoteid").map(|id| PublicKey::from_str(id)) {
+            Some(Ok(node_id)) => node_id,
+            _ => return HttpResponse::error(400, "invalid remoteid"),
+        };
+        let private = query.get("private").map(String::as_str) == Some("1");
+
+        self.state.lock().unwrap().channels.push((node_id, private));
+        HttpResponse::ok(&json!({ "status": "OK" }))
+    }
+
+    fn login(&self, k1: &str, query: &HashMap<String, String>) -> HttpResponse {
+        if query.get("k1").map(String::as_str) != Some(k1) {
+            return HttpResponse::error(400, "invalid k1");
+        }
+
+        let sig = query
+            .get("sig")
+            .and_then(|sig| Signature::from_str(sig).ok());
+        let key = query
+            .get("key")
+            .and_then(|key| PublicKey::from_str(key).ok());
+        let (sig, key) = match (sig, key) {
+            (Some(sig), Some(key)) => (sig, key),
+            _ => return HttpResponse::error(400, "missing signature"),
+        };
+
+        let msg = Message::from_digest(<[u8; 32]>::from_hex(k1).expect("k1 is 32 bytes hex"));
+        if Secp256k1::verification_only()
+            .verify_ecdsa(&msg, &sig, &key)
+            .is_err()
+        {
+            return HttpResponse::error(400, "invalid signature");
+        }
+
+        self.state.lock().unwrap().logins.push(key);
+        HttpResponse::ok(&json!({ "status": "OK" }))
+    }
+}
+
+/// Read the request head from `stream`, returning the request target and the headers,
+/// with lowercase names
+fn read_request(stream: &TcpStream) -> Option<(String, HashMap<String, String>)> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).ok()?;
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).ok()? == 0 || line == "\r\n" || line == "\n" {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+
+    let target = request_line.split_whitespace().nth(1)?.to_string();
+    Some((target, headers))
+}
+
+#[cfg(feature = "blocking")]
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lnurl::LnUrlKind;
+    use crate::pay::PayOptions;
+    use crate::{BlockingClient, Builder, LnUrlResponse, Response};
+    use bitcoin::secp256k1::SecretKey;
+
+    fn client() -> BlockingClient {
+        Builder::default().timeout(1).build_blocking().unwrap()
+    }
+
+    #[test]
+    fn test_pay_request() {
+        let server = MockLnUrlServer::start().unwrap();
+        let pay = server.add_pay_request("/pay", 1_000, 1_000_000, "coffee");
+        let client = client();
+
+        match client.make_request(&server.url("/pay")).unwrap() {
+            LnUrlResponse::LnUrlPayResponse(res) => assert_eq!(res, pay),
+            _ => panic!("Wrong response type"),
+        }
+
+        let invoice = client.get_invoice(&pay, 21_000, None, None).unwrap();
+        assert!(invoice.invoice().starts_with("lnbcrt210n1"));
+        let decoded = invoice.decoded().unwrap();
+        assert_eq!(decoded.amount_msats, Some(21_000));
+        assert_eq!(decoded.payee, server.node().node_id());
+        assert_eq!(
+            decoded.description_hash.map(|hash| hash.to_byte_array()),
+            Some(pay.metadata_hash())
+        );
+        assert_eq!(
+            server.requests(),
+            vec!["/pay".to_string(), "/pay/callback?amount=21000".to_string()]
+        );
+
+        let regtest = Builder::default()
+            .network(Network::Regtest)
+            .build_blocking()
+            .unwrap();
+        assert!(regtest.get_invoice(&pay, 21_000, None, None).is_ok());
+
+        let mainnet = Builder::default()
+            .network(Network::Bitcoin)
+            .build_blocking()
+            .unwrap();
+        assert!(matches!(
+            mainnet.get_invoice(&pay, 21_000, None, None),
+            Err(Error::InvoiceNetworkMismatch { .. })
+        ));
+
+        // the mock invoices expire after an hour
+        let patient = Builder::default()
+            .min_invoice_expiry(7_200)
+            .build_blocking()
+            .unwrap();
+        assert!(matches!(
+            patient.get_invoice(&pay, 21_000, None, None),
+            Err(Error::InvoiceExpiresSoon { .. })
+        ));
+    }
+
+    #[test]
+    fn test_lightning_address_identifier() {
+        let server = MockLnUrlServer::start().unwrap();
+        let ben = LightningAddress::new("ben@example.com").unwrap();
+        let pay = server.add_lightning_address(&ben);
+        let client = Builder::default()
+            .timeout(1)
+            .resolve("example.com", server.base_url())
+            .build_blocking()
+            .unwrap();
+
+        assert_eq!(client.resolve_lightning_address(&ben).unwrap(), pay);
+        let prepared = client
+            .pay_request(ben, 21_000, PayOptions::default())
+            .unwrap();
+        assert!(prepared.invoice.invoice().starts_with("lnbcrt210n1"));
+
+        // another address serving the pay request of ben
+        let mallory = LightningAddress::new("mallory@example.com").unwrap();
+        server.add_pay_response(&format!("{LNURLP_PATH_PREFIX}mallory"), pay);
+        assert!(matches!(
+            client.resolve_lightning_address(&mallory),
+            Err(Error::IdentifierMismatch { found: Some(ref found), .. }) if found == "ben@example.com"
+        ));
+        assert!(matches!(
+            client.pay_request(mallory, 21_000, PayOptions::default()),
+            Err(Error::IdentifierMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_keysend() {
+        let server = MockLnUrlServer::start().unwrap();
+        let ben = LightningAddress::new("ben@example.com").unwrap();
+        let keysend = server.add_keysend(&ben);
+        server.add_lightning_address(&ben);
+        let client = Builder::default()
+            .timeout(1)
+            .resolve("example.com", server.base_url())
+            .build_blocking()
+            .unwrap();
+
+        let resolved = client.resolve_keysend(&ben).unwrap();
+        assert_eq!(resolved, keysend);
+        assert_eq!(resolved.pubkey, server.node().node_id());
+
+        // only the pay request is served for alice
+        let alice = LightningAddress::new("alice@example.com").unwrap();
+        server.add_lightning_address(&alice);
+        assert!(client.resolve_keysend(&alice).is_err());
+        server.add_pay_response(
+            &format!("{KEYSEND_PATH_PREFIX}alice"),
+            client.resolve_lightning_address(&alice).unwrap(),
+        );
+        assert!(matches!(
+            client.resolve_keysend(&alice),
+            Err(Error::WrongTag {
+                expected: Tag::Keysend,
+                found: Tag::PayRequest,
+            })
+        ));
+    }
+
+    #[test]
+    fn test_misbehaviour() {
+        let server = MockLnUrlServer::start().unwrap();
+        let pay = server.add_pay_request("/pay", 1_000, 1_000_000, "coffee");
+        let client = client();
+
+        server.set_misbehaviour(Some(Misbehaviour::WrongAmount));
+        assert!(matches!(
+            client.get_invoice(&pay, 21_000, None, None),
+            Err(Error::InvoiceAmountMismatch {
+                requested_msats: 21_000,
+                invoice_msats: Some(22_000),
+            })
+        ));
+
+        server.set_misbehaviour(Some(Misbehaviour::ErrorResponse("nope".to_string())));
+        assert!(client.get_invoice(&pay, 21_000, None, None).is_err());
+
+        server.set_misbehaviour(Some(Misbehaviour::HttpStatus(503)));
+        assert!(client.make_request(&server.url("/pay")).is_err());
+
+        server.set_misbehaviour(Some(Misbehaviour::Slow(Duration::from_secs(2))));
+        assert!(client.make_request(&server.url("/pay")).is_err());
+
+        server.set_misbehaviour(None);
+        assert!(client.get_invoice(&pay, 21_000, None, None).is_ok());
+    }
+
+    #[test]
+    fn test_description_hash() {
+        let server = MockLnUrlServer::start().unwrap();
+        let pay = server.add_pay_request("/pay", 1_000, 1_000_000, "coffee");
+        let client = client();
+        let target = || url::Url::parse(&server.url("/pay")).unwrap();
+
+        let payment = client
+            .pay_request(target(), 21_000, PayOptions::default())
+            .unwrap();
+        assert_eq!(
+            payment.invoice.decoded().unwrap().description_hash,
+            Some(sha256::Hash::hash(pay.metadata.as_bytes()))
+        );
+
+        // zap invoices commit to the zap request, which must reach the service unaltered
+        let zap_request = r#"{"kind":9734,"content":"zap & #1 + more"}"#;
+        let zap = PayOptions {
+            zap_request: Some(zap_request.to_string()),
+            ..Default::default()
+        };
+        let payment = client.pay_request(target(), 21_000, zap.clone()).unwrap();
+        assert_eq!(
+            payment.invoice.decoded().unwrap().description_hash,
+            Some(sha256::Hash::hash(zap_request.as_bytes()))
+        );
+
+        server.set_misbehaviour(Some(Misbehaviour::WrongDescriptionHash));
+        assert!(matches!(
+            client.pay_request(target(), 21_000, PayOptions::default()),
+            Err(Error::InvoiceDescriptionHashMismatch { found: Some(_), .. })
+        ));
+        assert!(matches!(
+            client.pay_request(target(), 21_000, zap),
+            Err(Error::InvoiceDescriptionHashMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_withdraw_and_channel() {
+        let server = MockLnUrlServer::start().unwrap();
+        let withdrawal = server.add_withdraw_request("/withdraw", 10_000);
+        let channel = server.add_channel_request("/channel");
+        let client = client();
+
+        let wallet = MockLightningBackend::default();
+        let description = InvoiceDescription::Direct("withdraw".to_string());
+        let invoice = wallet.create_invoice_sync(5_000, &description, None);
+        let res = client.do_withdrawal(&withdrawal, &invoice.bolt11).unwrap();
+        assert_eq!(res, Response::Ok(()));
+        assert_eq!(server.withdrawals(), vec![invoice.bolt11]);
+
+        let res = client
+            .open_channel(&channel, wallet.node_id(), true)
+            .unwrap();
+        assert_eq!(res, Response::Ok(()));
+        assert_eq!(server.channels(), vec![(wallet.node_id(), true)]);
+    }
+
+    #[test]
+    fn test_auth() {
+        let server = MockLnUrlServer::start().unwrap();
+        let lnurl = server.add_auth("/auth");
+        assert!(lnurl.is_lnurl_auth());
+
+        let k1 = match lnurl.kind() {
+            LnUrlKind::Auth { k1, .. } => k1,
+            kind => panic!("expected an auth LNURL, got {:?}", kind),
+        };
+        let secp = Secp256k1::new();
+        let key = SecretKey::from_slice(&[7; 32]).unwrap();
+        let msg = Message::from_digest(<[u8; 32]>::from_hex(&k1).unwrap());
+        let sig = secp.sign_ecdsa(&msg, &key);
+        let pubkey = key.public_key(&secp);
+
+        let res = client().lnurl_auth(lnurl, sig, pubkey).unwrap();
+        assert_eq!(res, Response::Ok(()));
+        assert_eq!(server.logins(), vec![pubkey]);
+    }
+}
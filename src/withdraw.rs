@@ -1,7 +1,10 @@
-use crate::Tag;
-use serde::{Deserialize, Serialize};
+use crate::api::{take_nulls, validate_callback, validate_k1, validate_tag, without_set_nulls};
+use crate::{Error, Tag};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(remote = "Self")]
 pub struct WithdrawalResponse {
     /// A default withdrawal invoice description
     #[serde(rename = "defaultDescription")]
@@ -17,7 +20,112 @@ pub struct WithdrawalResponse {
     /// An optional field, defaults to 1 MilliSatoshi if not present,
     /// can not be less than 1 or more than `max_withdrawable`
     #[serde(rename = "minWithdrawable")]
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub min_withdrawable: Option<u64>,
     /// tag of the request
     pub tag: Tag,
+
+    /// Unknown fields of the response, see [`PayResponse`](crate::pay::PayResponse)
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl<'de> Deserialize<'de> for WithdrawalResponse {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut json = serde_json::Map::deserialize(deserializer)?;
+        let nulls = take_nulls(&mut json, &["minWithdrawable"]);
+        let mut withdrawal = WithdrawalResponse::deserialize(serde_json::Value::Object(json))
+            .map_err(D::Error::custom)?;
+        withdrawal.extra.extend(nulls);
+        Ok(withdrawal)
+    }
+}
+
+impl Serialize for WithdrawalResponse {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let set = [("minWithdrawable", self.min_withdrawable.is_some())];
+        match without_set_nulls(&self.extra, &set) {
+            Some(extra) => WithdrawalResponse::serialize(
+                &WithdrawalResponse {
+                    extra,
+                    ..self.clone()
+                },
+                serializer,
+            ),
+            None => WithdrawalResponse::serialize(self, serializer),
+        }
+    }
+}
+
+impl WithdrawalResponse {
+    /// Check that the callback is a url, `k1` is 32 bytes of hex and the limits satisfy
+    /// `1 <= min_withdrawable <= max_withdrawable`
+    pub fn validate(&self) -> Result<(), Error> {
+        validate_tag(&self.tag, Tag::WithdrawRequest)?;
+        validate_callback(&self.callback)?;
+        validate_k1(&self.k1)?;
+
+        let min = self.min_withdrawable.unwrap_or(1);
+        if min < 1 {
+            return Err(Error::InvalidField {
+                field: "minWithdrawable",
+                reason: "must be at least 1".to_string(),
+            });
+        }
+        if min > self.max_withdrawable {
+            return Err(Error::InvalidField {
+                field: "maxWithdrawable",
+                reason: format!(
+                    "{} is less than minWithdrawable {min}",
+                    self.max_withdrawable
+                ),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixtures::withdrawal;
+
+    #[test]
+    fn test_validate() {
+        assert!(withdrawal().validate().is_ok());
+
+        let invalid = [
+            WithdrawalResponse {
+                min_withdrawable: Some(0),
+                ..withdrawal()
+            },
+            WithdrawalResponse {
+                max_withdrawable: 999,
+                ..withdrawal()
+            },
+            WithdrawalResponse {
+                k1: "k1".to_string(),
+                ..withdrawal()
+            },
+            WithdrawalResponse {
+                k1: "voucher-7Tg8PQ7_x.~".to_string(),
+                ..withdrawal()
+            },
+            WithdrawalResponse {
+                callback: "ftp://example.com".to_string(),
+                ..withdrawal()
+            },
+        ];
+        for (response, field) in
+            invalid
+                .iter()
+                .zip(["minWithdrawable", "maxWithdrawable", "k1", "k1", "callback"])
+        {
+            match response.validate() {
+                Err(Error::InvalidField { field: f, .. }) => assert_eq!(f, field),
+                other => panic!("expected invalid {}, got {:?}", field, other),
+            }
+        }
+    }
 }